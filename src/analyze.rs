@@ -0,0 +1,130 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use rustc_errors::ErrorReported;
+use rustc_hash::FxHashMap;
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_interface::interface;
+use rustc_session::config::{
+    self, CrateType, ErrorOutputType, ExternEntry, ExternLocation, Externs, Input,
+};
+use rustc_session::search_paths::SearchPath;
+use rustc_session::utils::CanonicalizedPath;
+use rustc_session::DiagnosticOutput;
+use rustc_span::edition::DEFAULT_EDITION;
+use rustc_span::source_map::FileName;
+
+use crate::collect::collect;
+use crate::options::Options;
+use crate::stats::Stats;
+
+/// Analyses `input` as a crate on its own and returns its stats.
+///
+/// Rather than going through the rustc command line, the compiler is
+/// configured directly (see test/rustc-driver-example.rs). Errors in the
+/// input are reported as usual and give `Err`.
+pub fn analyze(input: Input, options: &Options) -> Result<Stats, ErrorReported> {
+    // The compiler runs on a thread of its own, so we enter this there.
+    let span = tracing::info_span!("analyze", input = %input.source_name());
+    let input_path = match &input {
+        Input::File(path) => Some(path.clone()),
+        Input::Str { .. } => None,
+    };
+    let config = interface::Config {
+        opts: config::Options {
+            maybe_sysroot: Some(PathBuf::from(crate::sysroot())),
+            // We stop after analysis anyway, and a library doesn't need `main`.
+            crate_types: vec![CrateType::Rlib],
+            edition: options
+                .edition
+                .as_ref()
+                .and_then(|edition| edition.parse().ok())
+                .unwrap_or(DEFAULT_EDITION),
+            externs: externs(&options.externs),
+            search_paths: options
+                .lib_paths
+                .iter()
+                .map(|path| SearchPath::from_cli_opt(path, ErrorOutputType::default()))
+                .collect(),
+            ..config::Options::default()
+        },
+        crate_cfg: interface::parse_cfgspecs(options.cfgs.clone()),
+        input,
+        input_path,
+        output_dir: None,
+        output_file: None,
+        file_loader: None,
+        diagnostic_output: DiagnosticOutput::Default,
+        stderr: None,
+        crate_name: None,
+        lint_caps: FxHashMap::default(),
+        register_lints: None,
+        override_queries: None,
+        registry: rustc_driver::diagnostics_registry(),
+    };
+
+    rustc_driver::catch_fatal_errors(|| {
+        interface::run_compiler(config, |compiler| {
+            let _enter = span.enter();
+            compiler.enter(|queries| {
+                let global_ctxt = &mut *queries.global_ctxt()?.peek_mut();
+                global_ctxt.enter(|tcx| {
+                    // Run the rest of analysis (type checking, etc.) so that
+                    // we don't collect stats from a crate with errors.
+                    tcx.analysis(LOCAL_CRATE)?;
+                    tracing::info!("analysis finished, collecting stats");
+                    Ok(collect(tcx, options))
+                })
+            })
+        })
+    })
+    .and_then(|result| result)
+}
+
+// Builds the compiler's map of extern crates from `--extern` specs, each of
+// which is `NAME=PATH` or just `NAME` (to be found in the library search path).
+fn externs(specs: &[String]) -> Externs {
+    let mut externs = BTreeMap::new();
+    for spec in specs {
+        let (name, path) = match spec.find('=') {
+            Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+            None => (&spec[..], None),
+        };
+
+        let entry = externs.entry(name.to_owned()).or_insert_with(|| ExternEntry {
+            location: ExternLocation::FoundInLibrarySearchDirectories,
+            is_private_dep: false,
+            add_prelude: true,
+        });
+        if let Some(path) = path {
+            let path = CanonicalizedPath::new(Path::new(path));
+            match &mut entry.location {
+                ExternLocation::ExactPaths(paths) => {
+                    paths.insert(path);
+                }
+                location => *location = ExternLocation::ExactPaths(iter::once(path).collect()),
+            }
+        }
+    }
+
+    Externs::new(externs)
+}
+
+/// Like `analyze`, but for source code in a string. `name` is used as the file
+/// name in locations and error messages.
+pub fn analyze_str(name: &str, src: String, options: &Options) -> Result<Stats, ErrorReported> {
+    let input = Input::Str {
+        name: FileName::Custom(name.to_owned()),
+        input: src,
+    };
+    analyze(input, options)
+}
@@ -0,0 +1,261 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Analysing many files in one run. rustc only takes a single input, so if we
+// are given more than one file, or a directory, we analyse each file as a
+// crate of its own and combine the stats.
+
+use std::fs;
+use std::io;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use glob::Pattern;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rustc_errors::ErrorReported;
+use rustc_session::config::Input;
+
+use crate::analyze;
+use crate::options::{Format, Options};
+use crate::stats::Stats;
+
+// rustc options which take their value as the following argument.
+const OPTIONS_WITH_VALUES: &[&str] = &[
+    "-A", "-C", "-D", "-F", "-L", "-W", "-Z", "-l", "-o", "--allow", "--cap-lints", "--cfg",
+    "--codegen", "--color", "--crate-name", "--crate-type", "--deny", "--edition", "--emit",
+    "--error-format", "--extern", "--forbid", "--json", "--out-dir", "--print",
+    "--remap-path-prefix", "--sysroot", "--target", "--warn",
+];
+
+/// The inputs on a rustc command line, i.e., the arguments which aren't
+/// options or option values.
+pub fn inputs(args: &[String]) -> Vec<PathBuf> {
+    let mut inputs = vec![];
+
+    // Skip the program name.
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if OPTIONS_WITH_VALUES.contains(&&**arg) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            inputs.push(PathBuf::from(arg));
+        }
+    }
+
+    inputs
+}
+
+/// Should `inputs` be analysed as a batch, rather than by rustc as usual?
+pub fn is_batch(inputs: &[PathBuf]) -> bool {
+    inputs.len() > 1 || inputs.iter().any(|input| input.is_dir())
+}
+
+/// The files to analyse in a batch.
+pub struct SourceFiles {
+    pub files: Vec<PathBuf>,
+    // Number of files left out because they match `--exclude-glob`.
+    pub excluded: usize,
+    // Files left out because they are bigger than `--max-file-size`, with
+    // their sizes.
+    pub too_big: Vec<(PathBuf, u64)>,
+}
+
+/// Expands directories in `inputs` into the `.rs` files they contain, then
+/// drops any files matching `--exclude-glob` or bigger than `--max-file-size`.
+pub fn source_files(inputs: &[PathBuf], options: &Options) -> Result<SourceFiles, String> {
+    let patterns = options
+        .exclude_globs
+        .iter()
+        .map(|glob| Pattern::new(glob).map_err(|e| format!("invalid glob `{}`: {}", glob, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut files = vec![];
+    for input in inputs {
+        find_source_files(input, &mut files)
+            .map_err(|e| format!("couldn't read `{}`: {}", input.display(), e))?;
+    }
+
+    let found = files.len();
+    files.retain(|file| {
+        let file = file.strip_prefix(".").unwrap_or(file);
+        !patterns.iter().any(|pattern| pattern.matches_path(file))
+    });
+    let excluded = found - files.len();
+
+    // We check sizes before handing anything to the compiler, a huge
+    // generated file can take a long time (and a lot of memory) to analyse.
+    let mut too_big = vec![];
+    if let Some(max_file_size) = options.max_file_size {
+        let mut small_enough = vec![];
+        for file in files {
+            let size = fs::metadata(&file)
+                .map_err(|e| format!("couldn't read `{}`: {}", file.display(), e))?
+                .len();
+            if size > max_file_size {
+                too_big.push((file, size));
+            } else {
+                small_enough.push(file);
+            }
+        }
+        files = small_enough;
+    }
+
+    Ok(SourceFiles {
+        files,
+        excluded,
+        too_big,
+    })
+}
+
+fn find_source_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_owned());
+        return Ok(());
+    }
+
+    // Sort so we always analyse (and report) files in the same order.
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || entry.extension().map_or(false, |ext| ext == "rs") {
+            find_source_files(&entry, files)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks a random `--sample-rate` fraction of `files` (at least one), keeping
+/// them in order. Without `--sample-rate`, that's all of them.
+pub fn sample(mut files: Vec<PathBuf>, options: &Options) -> Vec<PathBuf> {
+    let rate = match options.sample_rate {
+        Some(rate) if rate < 1.0 => rate,
+        _ => return files,
+    };
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let count = ((files.len() as f64 * rate).ceil() as usize).max(1).min(files.len());
+    let mut picked = rand::seq::index::sample(&mut rng, files.len(), count).into_vec();
+    picked.sort();
+    picked
+        .into_iter()
+        .map(|i| mem::take(&mut files[i]))
+        .collect()
+}
+
+/// Analyses each of `files` as a crate of its own and combines their stats.
+/// Files which fail to compile (or crash the compiler) are reported (on
+/// stderr) and left out.
+///
+/// With `--fail-fast`, we stop at the first file which fails and return it as
+/// the error instead.
+pub fn analyze_files(files: &[PathBuf], options: &Options) -> Result<Stats, PathBuf> {
+    let mut stats = Stats::default();
+    for (_, file_stats) in analyze_each(files, options)? {
+        stats.merge(file_stats);
+    }
+    Ok(stats)
+}
+
+/// Like `analyze_files`, but keeps the stats for each file separate, in the
+/// same order as `files`.
+///
+/// Up to `--jobs` files are analysed at once, each on its own thread.
+pub fn analyze_each(
+    files: &[PathBuf],
+    options: &Options,
+) -> Result<Vec<(PathBuf, Stats)>, PathBuf> {
+    let jobs = options.jobs.unwrap_or_else(num_cpus::get).min(files.len()).max(1);
+    let files = Arc::new(files.to_vec());
+    let options = Arc::new(options.clone());
+    let next_file = Arc::new(AtomicUsize::new(0));
+    // Set when a file fails with `--fail-fast`, so no more are started.
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = (0..jobs)
+        .map(|_| {
+            let files = files.clone();
+            let options = options.clone();
+            let next_file = next_file.clone();
+            let stop = stop.clone();
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let index = next_file.fetch_add(1, Ordering::SeqCst);
+                let file = match files.get(index) {
+                    Some(file) => file,
+                    None => break,
+                };
+                let span = tracing::info_span!("file", path = %file.display());
+                let _enter = span.enter();
+                // An ICE is a panic, which would otherwise take the whole
+                // thread (and so the rest of its files) down with it.
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    analyze(Input::File(file.clone()), &options)
+                }))
+                .unwrap_or(Err(ErrorReported));
+                tracing::info!(ok = result.is_ok(), "finished file");
+                if result.is_err() && options.fail_fast {
+                    stop.store(true, Ordering::SeqCst);
+                }
+                sender.send((index, result)).unwrap();
+            })
+        })
+        .collect();
+    drop(sender);
+
+    // Combine in file order, so the output doesn't depend on which thread
+    // finished first.
+    let mut results: Vec<_> = receiver.iter().collect();
+    results.sort_by_key(|(index, _)| *index);
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let mut each = vec![];
+    for (index, result) in results {
+        match result {
+            Ok(file_stats) => each.push((files[index].clone(), file_stats)),
+            Err(_) if options.fail_fast => return Err(files[index].clone()),
+            Err(_) => eprintln!("error: couldn't analyse `{}`", files[index].display()),
+        }
+    }
+
+    Ok(each)
+}
+
+/// Where `--output-dir` puts the stats for `file`, i.e., the same path under
+/// `dir`, with the format's extension added (`src/foo.rs` gives
+/// `dir/src/foo.rs.json`). Any root or `..` in `file` is left out, so the
+/// result is always inside `dir`.
+pub fn output_path(dir: &Path, file: &Path, format: Format) -> PathBuf {
+    let mut path = dir.to_owned();
+    for component in file.components() {
+        if let Component::Normal(name) = component {
+            path.push(name);
+        }
+    }
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(format.extension());
+    path.set_file_name(name);
+    path
+}
@@ -0,0 +1,43 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// How often code changes, according to git. This is only used with
+// `--with-churn`, since it runs git once per function and so is slow.
+
+use std::path::Path;
+use std::process::Command;
+
+// The number of commits which touched lines `first` to `last` (inclusive,
+// starting from 1) of `file`. `None` if git couldn't tell us, e.g., because
+// the file isn't in a git repository.
+pub(crate) fn churn(file: &Path, first: usize, last: usize) -> Option<usize> {
+    // Run git from the file's directory so we find its repository, even if
+    // that isn't the one we were run from.
+    let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let name = file.file_name()?;
+
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let mut range = format!("-L{},{}:", first, last);
+    range.push_str(&name.to_string_lossy());
+    let output = command
+        .args(&["log", "--format=commit:%H"])
+        .arg(range)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // `-L` always shows the diff for each commit, but no line of a diff
+    // starts with `commit:`.
+    let output = String::from_utf8_lossy(&output.stdout);
+    Some(output.lines().filter(|line| line.starts_with("commit:")).count())
+}
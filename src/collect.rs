@@ -0,0 +1,1436 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+use std::path::PathBuf;
+
+use rustc_ast::ast;
+use rustc_hir as hir;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, DefIdSet, LOCAL_CRATE};
+use rustc_hir::intravisit::{self, FnKind, NestedVisitorMap, Visitor};
+use rustc_middle::hir::map::Map;
+use rustc_middle::ty::subst::GenericArgKind;
+use rustc_middle::ty::{self, ParamEnv, Ty, TyCtxt, TypeckResults};
+use rustc_span::hygiene::{ExpnKind, MacroKind};
+use rustc_span::symbol::{kw, sym, Ident, Symbol};
+use rustc_span::Span;
+use rustc_target::spec::abi::Abi;
+
+use crate::churn;
+use crate::doctest;
+use crate::options::Options;
+use crate::stats::{
+    Conversion, Deprecation, FnStats, IndexOp, Inline, Layout, NamingViolation, Stats, Stub,
+};
+
+/// Walks the HIR of the whole crate and returns the stats collected.
+pub fn collect(tcx: TyCtxt<'_>, options: &Options) -> Stats {
+    let module_path = options.module_path.as_deref().map(crate_relative);
+    if let Some(module_path) = module_path {
+        let found = module_path.is_empty()
+            || tcx.hir().krate().items.values().any(|item| {
+                let def_id = tcx.hir().local_def_id(item.hir_id).to_def_id();
+                matches!(item.kind, hir::ItemKind::Mod(..))
+                    && crate_relative(&tcx.def_path_str(def_id)) == module_path
+            });
+        if !found {
+            tcx.sess.fatal(&format!("couldn't find module `{}`", module_path));
+        }
+    }
+
+    // Everything which is instantiated at least once. Working this out means
+    // collecting the mono items, which is slow, so only do it if asked.
+    let mono_items = if options.uninstantiated_generics {
+        Some(tcx.collect_and_partition_mono_items(LOCAL_CRATE).0)
+    } else {
+        None
+    };
+
+    let mut collector = StatsCollector {
+        tcx,
+        options,
+        stats: Stats::default(),
+        current_fn: None,
+        typeck_results: None,
+        methods: BTreeMap::new(),
+        in_bound: false,
+        in_parenthesized: false,
+        current_const: None,
+        mono_items,
+        doctests: vec![],
+        seen_len_check: false,
+    };
+    let krate = tcx.hir().krate();
+    if module_path.map_or(true, str::is_empty) {
+        // Crate docs (`//!` in the crate root) don't belong to any item.
+        collector.record_doctests(krate.item.attrs);
+        intravisit::walk_crate(&mut collector, krate);
+    } else {
+        // As `walk_crate`, but leaving out the crate's attributes and
+        // exported macros, which aren't in any module but the root.
+        collector.visit_mod(&krate.item.module, krate.item.span, hir::CRATE_HIR_ID);
+    }
+    collector.find_accessor_pairs();
+    let doctests = mem::take(&mut collector.doctests);
+
+    let mut stats = collector.stats;
+    // Lints are run as part of analysis, so all the warnings we're going to
+    // get have been emitted by now.
+    stats.warnings = tcx.sess.diagnostic().warn_count();
+    analyze_doctests(&mut stats, doctests, options);
+    stats.sort();
+    stats
+}
+
+// Analyses each doctest as a crate of its own. A doctest which uses the crate
+// it documents won't compile, since we don't build that crate for it to link
+// against, so those are only counted.
+fn analyze_doctests(stats: &mut Stats, doctests: Vec<(String, String)>, options: &Options) {
+    if doctests.is_empty() {
+        return;
+    }
+
+    let mut options = options.clone();
+    // Doc comments in doctests aren't doctests, and the rest don't make sense
+    // for source which isn't in a file.
+    options.doctests = false;
+    options.module_path = None;
+    options.with_churn = false;
+
+    let mut doctest_stats = Stats::default();
+    for (location, source) in doctests {
+        tracing::info!(%location, "analysing doctest");
+        stats.doctests += 1;
+        let name = format!("doctest at {}", location);
+        match crate::analyze_str(&name, source, &options) {
+            Ok(stats) => doctest_stats.merge(stats),
+            Err(_) => stats.failed_doctests += 1,
+        }
+    }
+    stats.doctest_stats = Some(Box::new(doctest_stats));
+}
+
+/// Is `item` in the module given by `--module-path` (or its submodules),
+/// treating a module as being inside itself? Everything is if there's no
+/// `--module-path`.
+pub fn in_module_path(tcx: TyCtxt<'_>, options: &Options, item: &hir::Item<'_>) -> bool {
+    let module_path = match options.module_path.as_deref().map(crate_relative) {
+        Some(module_path) if !module_path.is_empty() => module_path,
+        _ => return true,
+    };
+
+    let module = match item.kind {
+        hir::ItemKind::Mod(..) => tcx.hir().local_def_id(item.hir_id),
+        _ => tcx.parent_module(item.hir_id),
+    };
+    let module = tcx.def_path_str(module.to_def_id());
+    let module = crate_relative(&module);
+    module == module_path || module.starts_with(&format!("{}::", module_path))
+}
+
+// Like `StupidVisitor`, but walks the HIR (after analysis) rather than the AST.
+struct StatsCollector<'tcx, 'o> {
+    tcx: TyCtxt<'tcx>,
+    options: &'o Options,
+    stats: Stats,
+    // Index into `stats.functions` of the function we are currently inside.
+    current_fn: Option<usize>,
+    // Type information for the body we are currently inside.
+    typeck_results: Option<&'tcx TypeckResults<'tcx>>,
+    // The methods in inherent and trait impls, keyed by self type. Each method
+    // is a name and the kind of `self` it takes.
+    methods: BTreeMap<String, Vec<(String, hir::ImplicitSelfKind)>>,
+    // Are we inside a bound, e.g., `Iterator<Item = u8>` in `T: Iterator<Item = u8>`?
+    in_bound: bool,
+    // Are we inside parenthesized generic args, e.g., `(u8) -> u16` in
+    // `Fn(u8) -> u16`?
+    in_parenthesized: bool,
+    // Path to the const or static whose initializer we are currently inside.
+    current_const: Option<String>,
+    // Functions which are codegened, with `--uninstantiated-generics`.
+    mono_items: Option<&'tcx DefIdSet>,
+    // The source of each doctest found, with the location of its doc comment.
+    doctests: Vec<(String, String)>,
+    // Have we seen a `.len()` call in the function we are currently inside?
+    seen_len_check: bool,
+}
+
+impl<'tcx, 'o> StatsCollector<'tcx, 'o> {
+    // A readable, crate-relative path for an item, e.g., `foo::Bar`.
+    fn item_path(&self, hir_id: hir::HirId) -> String {
+        self.tcx
+            .def_path_str(self.tcx.hir().local_def_id(hir_id).to_def_id())
+    }
+
+    fn trait_name(&self, trait_ref: &hir::TraitRef<'_>) -> String {
+        match trait_ref.trait_def_id() {
+            Some(def_id) => self.tcx.def_path_str(def_id),
+            None => "<unknown trait>".to_owned(),
+        }
+    }
+
+    fn type_name(&self, ty: Ty<'tcx>) -> String {
+        self.abbreviate(ty.to_string())
+    }
+
+    // Abbreviates the paths in a printed type or bound, unless asked for full
+    // paths.
+    fn abbreviate(&self, s: String) -> String {
+        if self.options.full_paths {
+            s
+        } else {
+            abbreviate_paths(&s)
+        }
+    }
+
+    fn location(&self, span: Span) -> String {
+        let loc = self.tcx.sess.source_map().lookup_char_pos(span.lo());
+        format!("{}:{}:{}", loc.file.name, loc.line, loc.col.0 + 1)
+    }
+
+    // Records a name for the longest names and, if asked for, checks it
+    // follows the naming convention for its kind.
+    fn check_name(&mut self, ident: Ident, kind: &str, case: Case, span: Span) {
+        // Names generated by macros aren't the user's fault.
+        if span.from_expansion() {
+            return;
+        }
+
+        let name = ident.as_str().to_string();
+        let location = self.location(span);
+        if self.options.check_naming && !case.matches(&name) {
+            self.stats.naming_violations.push(NamingViolation {
+                name: name.clone(),
+                kind: kind.to_owned(),
+                expected: case.name().to_owned(),
+                location: location.clone(),
+            });
+        }
+        self.stats.longest_names.push((name, location));
+    }
+
+    fn record_layout(&mut self, item: &hir::Item<'_>, generics: &hir::Generics<'_>) {
+        // Lifetimes don't affect layout, but type and const parameters do.
+        let generic = generics
+            .params
+            .iter()
+            .any(|param| !matches!(param.kind, hir::GenericParamKind::Lifetime { .. }));
+        let layout = if generic {
+            Layout::Generic
+        } else {
+            let ty = self
+                .tcx
+                .type_of(self.tcx.hir().local_def_id(item.hir_id).to_def_id());
+            match self.tcx.layout_of(ParamEnv::reveal_all().and(ty)) {
+                Ok(layout) => Layout::Known {
+                    size: layout.size.bytes(),
+                    align: layout.align.abi.bytes(),
+                },
+                Err(e) => Layout::Error(e.to_string()),
+            }
+        };
+
+        self.stats.layouts.push((self.item_path(item.hir_id), layout));
+    }
+
+    // Records the traits used as trait objects anywhere in the item's fields,
+    // e.g., `Box<dyn Fn()>` or `Vec<&dyn Debug>`.
+    fn record_trait_object_fields(&mut self, item: &hir::Item<'_>) {
+        let def_id = self.tcx.hir().local_def_id(item.hir_id).to_def_id();
+        let mut traits = BTreeSet::new();
+        for field in self.tcx.adt_def(def_id).all_fields() {
+            for arg in self.tcx.type_of(field.did).walk() {
+                if let GenericArgKind::Type(ty) = arg.unpack() {
+                    if let ty::Dynamic(predicates, _) = ty.kind() {
+                        // `dyn Send` and the like have no principal trait.
+                        if let Some(trait_def_id) = predicates.principal_def_id() {
+                            traits.insert(self.tcx.def_path_str(trait_def_id));
+                        }
+                    }
+                }
+            }
+        }
+
+        if !traits.is_empty() {
+            self.stats
+                .trait_object_fields
+                .push((self.item_path(item.hir_id), traits.into_iter().collect()));
+        }
+    }
+
+    // Records whether an item of the given kind is `#[must_use]` or
+    // `#[deprecated]`, and if it is deprecated, since when and why.
+    fn record_lifecycle(&mut self, hir_id: hir::HirId, kind: &str, attrs: &[ast::Attribute]) {
+        if attrs.iter().any(|attr| attr.has_name(sym::must_use)) {
+            *self.stats.must_use.entry(kind.to_owned()).or_insert(0) += 1;
+        }
+
+        // Only the item's own attribute, not one inherited from its parent.
+        if !attrs.iter().any(|attr| attr.has_name(sym::deprecated)) {
+            return;
+        }
+        let def_id = self.tcx.hir().local_def_id(hir_id).to_def_id();
+        if let Some(deprecation) = self.tcx.lookup_deprecation(def_id) {
+            *self.stats.deprecated.entry(kind.to_owned()).or_insert(0) += 1;
+            self.stats.deprecations.push(Deprecation {
+                name: self.item_path(hir_id),
+                location: self.location(self.tcx.hir().span(hir_id)),
+                kind: kind.to_owned(),
+                since: deprecation.since.map(|since| since.to_string()),
+                note: deprecation.note.map(|note| note.to_string()),
+            });
+        }
+    }
+
+    // Records the impl `hir_id` if it is of `From`, `Into`, `TryFrom`, or
+    // `TryInto`, along with the types it converts from and to.
+    fn record_conversion(&mut self, hir_id: hir::HirId) {
+        let def_id = self.tcx.hir().local_def_id(hir_id).to_def_id();
+        let trait_ref = match self.tcx.impl_trait_ref(def_id) {
+            Some(trait_ref) => trait_ref,
+            None => return,
+        };
+        if self.tcx.crate_name(trait_ref.def_id.krate) != sym::core {
+            return;
+        }
+        let name = self.tcx.item_name(trait_ref.def_id);
+        // `From<T> for U` converts `T` into `U`, `Into<U> for T` the same.
+        // Other traits may not have a type parameter, so only look for one
+        // once we know it's a conversion.
+        let self_ty = trait_ref.self_ty();
+        let (from, to) = match &*name.as_str() {
+            "From" | "TryFrom" => (trait_ref.substs.type_at(1), self_ty),
+            "Into" | "TryInto" => (self_ty, trait_ref.substs.type_at(1)),
+            _ => return,
+        };
+        self.stats.conversions.push(Conversion {
+            trait_name: name.to_string(),
+            from: self.type_name(from),
+            to: self.type_name(to),
+        });
+    }
+
+    // Records indexing or slicing which could panic, i.e., anything but
+    // `[..]`. Indexing in consts is checked when it is evaluated, so only
+    // function bodies count.
+    fn record_index(&mut self, expr: &hir::Expr<'_>, index: &hir::Expr<'_>) {
+        let current_fn = match self.current_fn {
+            Some(current_fn) => current_fn,
+            None => return,
+        };
+
+        // Slicing is indexing by a range.
+        let lang_items = self.tcx.lang_items();
+        let index_ty = self.typeck_results.map(|results| results.expr_ty(index).kind());
+        let range = match index_ty {
+            Some(ty::Adt(def, _)) => Some(def.did),
+            _ => None,
+        };
+        if range.is_some() && range == lang_items.range_full_struct() {
+            return;
+        }
+        let ranges = [
+            lang_items.range_struct(),
+            lang_items.range_from_struct(),
+            lang_items.range_to_struct(),
+            lang_items.range_inclusive_struct(),
+            lang_items.range_to_inclusive_struct(),
+        ];
+        let slice = range.is_some() && ranges.contains(&range);
+
+        let fn_stats = &mut self.stats.functions[current_fn];
+        fn_stats.index_ops += 1;
+        let function = fn_stats.name.clone();
+        self.stats.index_ops.push(IndexOp {
+            location: self.location(expr.span),
+            function,
+            slice,
+            len_checked: self.seen_len_check,
+        });
+    }
+
+    // Keeps the doctests in the doc comments among `attrs`, with `--doctests`.
+    fn record_doctests(&mut self, attrs: &[ast::Attribute]) {
+        if !self.options.doctests {
+            return;
+        }
+
+        // Each line of a `///` comment is its own attribute.
+        let docs: Vec<_> = attrs
+            .iter()
+            .filter(|attr| !attr.span.from_expansion())
+            .filter_map(|attr| attr.doc_str().map(|doc| (attr.span, doc)))
+            .collect();
+        let span = match docs.first() {
+            Some(&(span, _)) => span,
+            None => return,
+        };
+        let doc: Vec<_> = docs.iter().map(|(_, doc)| doc.as_str().to_string()).collect();
+        let location = self.location(span);
+        for source in doctest::extract(&doc.join("\n")) {
+            self.doctests.push((location.clone(), source));
+        }
+    }
+
+    // Records how the method `hir_id` of `owner` (a type or trait) takes
+    // `self`. We look at the type of the first parameter rather than the HIR's
+    // `implicit_self`, since that doesn't cover `self: Box<Self>` and friends.
+    fn record_receiver(&mut self, owner: String, hir_id: hir::HirId) {
+        let def_id = self.tcx.hir().local_def_id(hir_id).to_def_id();
+        let receiver = if self.tcx.associated_item(def_id).fn_has_self_parameter {
+            let self_arg = self.tcx.fn_sig(def_id).inputs().skip_binder()[0];
+            match self_arg.kind() {
+                ty::Ref(_, _, hir::Mutability::Not) => "by ref",
+                ty::Ref(_, _, hir::Mutability::Mut) => "by mut ref",
+                ty::Adt(def, _) if is_smart_pointer(self.tcx, def.did) => "by box/pin",
+                _ => "by value",
+            }
+        } else {
+            "no receiver"
+        };
+        *self
+            .stats
+            .receivers
+            .entry(owner)
+            .or_default()
+            .entry(receiver.to_owned())
+            .or_insert(0) += 1;
+    }
+
+    // Finds pairs of methods like `iter(&self)` and `iter_mut(&mut self)`, or
+    // `as_ref(&self)` and `as_mut(&mut self)`, on the same type.
+    fn find_accessor_pairs(&mut self) {
+        for (self_ty, methods) in &self.methods {
+            let takes = |name: &str, kind: hir::ImplicitSelfKind| {
+                methods
+                    .iter()
+                    .any(|(method, receiver)| method == name && *receiver == kind)
+            };
+
+            for (name, receiver) in methods {
+                if *receiver != hir::ImplicitSelfKind::MutRef {
+                    continue;
+                }
+                let base = match name.strip_suffix("_mut") {
+                    Some(base) => base,
+                    None => continue,
+                };
+                let by_ref = [base.to_owned(), format!("{}_ref", base)]
+                    .iter()
+                    .find(|by_ref| takes(by_ref, hir::ImplicitSelfKind::ImmRef))
+                    .cloned();
+                if let Some(by_ref) = by_ref {
+                    self.stats
+                        .accessor_pairs
+                        .push((self_ty.clone(), by_ref, name.clone()));
+                }
+            }
+        }
+    }
+
+    // The function called by a call or method call expression, if we know
+    // it statically.
+    fn callee(&self, expr: &hir::Expr<'_>) -> Option<DefId> {
+        let typeck_results = self.typeck_results?;
+        match expr.kind {
+            hir::ExprKind::Call(callee, _) => match callee.kind {
+                hir::ExprKind::Path(ref qpath) => {
+                    match typeck_results.qpath_res(qpath, callee.hir_id) {
+                        Res::Def(DefKind::Fn, def_id) | Res::Def(DefKind::AssocFn, def_id) => {
+                            Some(def_id)
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            hir::ExprKind::MethodCall(..) => typeck_results.type_dependent_def_id(expr.hir_id),
+            _ => None,
+        }
+    }
+
+    // How many commits touched the lines of `span`, see `--with-churn`.
+    fn churn(&self, span: Span) -> Option<usize> {
+        if span.from_expansion() {
+            return None;
+        }
+        let source_map = self.tcx.sess.source_map();
+        let lo = source_map.lookup_char_pos(span.lo());
+        let hi = source_map.lookup_char_pos(span.hi());
+        let file = PathBuf::from(lo.file.name.to_string());
+        churn::churn(&file, lo.line, hi.line)
+    }
+
+    fn current_fn(&mut self) -> Option<&mut FnStats> {
+        match self.current_fn {
+            Some(index) => Some(&mut self.stats.functions[index]),
+            None => None,
+        }
+    }
+}
+
+impl<'tcx, 'o> Visitor<'tcx> for StatsCollector<'tcx, 'o> {
+    type Map = Map<'tcx>;
+
+    // Unlike the AST, the HIR stores nested items and bodies out of line. By
+    // giving the walker the HIR map we ask it to visit all of them too.
+    fn nested_visit_map(&mut self) -> NestedVisitorMap<Self::Map> {
+        NestedVisitorMap::All(self.tcx.hir())
+    }
+
+    fn visit_item(&mut self, item: &'tcx hir::Item<'tcx>) {
+        if !in_module_path(self.tcx, self.options, item) {
+            // We still need to look inside modules in case the one we want is
+            // in there, but nothing else counts, not even their attributes.
+            if let hir::ItemKind::Mod(ref module) = item.kind {
+                for &item_id in module.item_ids {
+                    self.visit_nested_item(item_id);
+                }
+            }
+            return;
+        }
+
+        let kind = item_kind_name(&item.kind);
+        tracing::debug!(item = %self.item_path(item.hir_id), kind, "visiting item");
+        *self.stats.items.entry(kind.to_owned()).or_insert(0) += 1;
+        self.record_lifecycle(item.hir_id, kind, item.attrs);
+        self.record_doctests(item.attrs);
+
+        if self.options.group_by_file {
+            // Use the file the item's span points in to, rather than the
+            // module it is declared in; these differ for inline modules and
+            // `include!`.
+            let file = self.tcx.sess.source_map().span_to_filename(item.span);
+            *self
+                .stats
+                .items_by_file
+                .entry(file.to_string())
+                .or_default()
+                .entry(kind.to_owned())
+                .or_insert(0) += 1;
+        }
+
+        match item.kind {
+            // These have no visibility of their own.
+            hir::ItemKind::Impl { .. } | hir::ItemKind::ForeignMod(..) => {}
+            // Nor did the user give one to items from macros, e.g., the
+            // injected `extern crate std;` and prelude `use`.
+            _ if item.span.from_expansion() => {}
+            _ => {
+                let visibility = visibility_name(&item.vis.node);
+                *self.stats.visibilities.entry(visibility.to_owned()).or_insert(0) += 1;
+            }
+        }
+
+        let case = match item.kind {
+            hir::ItemKind::Fn(..) | hir::ItemKind::Mod(..) => Some(Case::Snake),
+            hir::ItemKind::Static(..) | hir::ItemKind::Const(..) => Some(Case::ScreamingSnake),
+            hir::ItemKind::Struct(..)
+            | hir::ItemKind::Enum(..)
+            | hir::ItemKind::Union(..)
+            | hir::ItemKind::Trait(..)
+            | hir::ItemKind::TraitAlias(..)
+            | hir::ItemKind::TyAlias(..) => Some(Case::Camel),
+            // Either no name or one we don't choose.
+            _ => None,
+        };
+        if let Some(case) = case {
+            self.check_name(item.ident, kind, case, item.span);
+        }
+
+        if let hir::ItemKind::Trait(_, _, _, bounds, _) = item.kind {
+            let supertraits = bounds
+                .iter()
+                .filter_map(|bound| match bound {
+                    hir::GenericBound::Trait(poly_trait_ref, _) => {
+                        poly_trait_ref.trait_ref.trait_def_id()
+                    }
+                    // Lifetime bounds (`trait Foo: 'static`) aren't supertraits.
+                    hir::GenericBound::Outlives(_) => None,
+                })
+                .map(|def_id| self.tcx.def_path_str(def_id))
+                .collect();
+            self.stats
+                .supertraits
+                .push((self.item_path(item.hir_id), supertraits));
+        }
+
+        match item.kind {
+            hir::ItemKind::Struct(_, ref generics)
+            | hir::ItemKind::Enum(_, ref generics)
+            | hir::ItemKind::Union(_, ref generics) => {
+                self.record_layout(item, generics);
+                self.record_trait_object_fields(item);
+            }
+            _ => {}
+        }
+
+        if let hir::ItemKind::Impl { .. } = item.kind {
+            let self_ty = self
+                .tcx
+                .type_of(self.tcx.hir().local_def_id(item.hir_id).to_def_id());
+            // Impls for references and smart pointers are listed with the
+            // trait (if any), e.g., `Display for &Foo`.
+            let description = |this: &Self| {
+                let self_ty = this.type_name(self_ty);
+                match item.kind {
+                    hir::ItemKind::Impl { of_trait: Some(ref trait_ref), .. } => {
+                        format!("{} for {}", this.trait_name(trait_ref), self_ty)
+                    }
+                    _ => self_ty,
+                }
+            };
+            match self_ty.kind() {
+                ty::Ref(..) => {
+                    let description = description(self);
+                    self.stats.reference_impls.push(description);
+                }
+                ty::Adt(def, _) if is_smart_pointer(self.tcx, def.did) => {
+                    let description = description(self);
+                    self.stats.smart_pointer_impls.push(description);
+                }
+                _ => self.stats.value_impls += 1,
+            }
+
+            let derived = item
+                .attrs
+                .iter()
+                .any(|attr| attr.has_name(sym::automatically_derived));
+
+            // Bounds on the impl which aren't implied by the type's own, e.g.,
+            // `T: Clone` in `impl<T: Clone> Foo<T>` when `Foo` is just
+            // `struct Foo<T>`. Derives always add these, so skip them.
+            if let (ty::Adt(def, substs), false) = (self_ty.kind(), derived) {
+                let impl_def_id = self.tcx.hir().local_def_id(item.hir_id).to_def_id();
+                let type_predicates = self
+                    .tcx
+                    .predicates_of(def.did)
+                    .instantiate(self.tcx, substs)
+                    .predicates;
+                // Every type parameter gets an implicit `T: Sized`, which
+                // only counts if it was written out.
+                let written_sized = match item.kind {
+                    hir::ItemKind::Impl { ref generics, .. } => sized_params(self.tcx, generics),
+                    _ => vec![],
+                };
+                let sized_trait = self.tcx.lang_items().sized_trait();
+                let extra: Vec<_> = self
+                    .tcx
+                    .predicates_of(impl_def_id)
+                    .predicates
+                    .iter()
+                    .filter(|(predicate, _)| !type_predicates.contains(predicate))
+                    .filter(|(predicate, _)| match predicate.to_opt_poly_trait_ref() {
+                        Some(trait_ref) if Some(trait_ref.def_id()) == sized_trait => {
+                            match trait_ref.skip_binder().self_ty().kind() {
+                                ty::Param(param) => written_sized.contains(&param.name),
+                                _ => false,
+                            }
+                        }
+                        _ => true,
+                    })
+                    .map(|(predicate, _)| self.abbreviate(predicate.to_string()))
+                    .collect();
+                if !extra.is_empty() {
+                    let description = description(self);
+                    self.stats.extra_bound_impls.push((description, extra));
+                }
+            }
+
+            let self_ty = self.type_name(self_ty);
+            let counts = self.stats.impls.entry(self_ty).or_default();
+            if derived {
+                counts.derived += 1;
+            } else {
+                counts.hand_written += 1;
+            }
+        }
+
+        if let hir::ItemKind::Impl { of_trait: Some(ref trait_ref), self_ty, .. } = item.kind {
+            if is_type_param(self_ty) {
+                self.stats.blanket_impls.push(self.trait_name(trait_ref));
+            } else {
+                self.stats.concrete_trait_impls += 1;
+            }
+
+            if trait_ref.trait_def_id() == self.tcx.lang_items().drop_trait() {
+                let self_ty = self
+                    .tcx
+                    .type_of(self.tcx.hir().local_def_id(item.hir_id).to_def_id());
+                self.stats.drop_impls.push(self.type_name(self_ty));
+            }
+
+            self.record_conversion(item.hir_id);
+        }
+
+        // Paths in a const or static's initializer may refer to other consts
+        // or statics. Nested items have their own initializers, if any.
+        let const_path = match item.kind {
+            hir::ItemKind::Const(..) | hir::ItemKind::Static(..) => {
+                Some(self.item_path(item.hir_id))
+            }
+            _ => None,
+        };
+        let outer_const = mem::replace(&mut self.current_const, const_path);
+        // Items inside a function body (e.g., a `const`) aren't part of the
+        // function.
+        let outer_fn = self.current_fn.take();
+        let outer_len_check = mem::replace(&mut self.seen_len_check, false);
+        intravisit::walk_item(self, item);
+        self.current_const = outer_const;
+        self.current_fn = outer_fn;
+        self.seen_len_check = outer_len_check;
+    }
+
+    fn visit_trait_item(&mut self, item: &'tcx hir::TraitItem<'tcx>) {
+        let (kind, case) = match item.kind {
+            hir::TraitItemKind::Fn(..) => ("method", Case::Snake),
+            hir::TraitItemKind::Const(..) => ("associated const", Case::ScreamingSnake),
+            hir::TraitItemKind::Type(..) => ("associated type", Case::Camel),
+        };
+        self.check_name(item.ident, kind, case, item.span);
+        self.record_lifecycle(item.hir_id, kind, item.attrs);
+        self.record_doctests(item.attrs);
+        tracing::debug!(item = %self.item_path(item.hir_id), kind, "visiting trait item");
+
+        if let hir::TraitItemKind::Fn(..) = item.kind {
+            let trait_id = self.tcx.hir().get_parent_item(item.hir_id);
+            self.record_receiver(self.item_path(trait_id), item.hir_id);
+        }
+
+        intravisit::walk_trait_item(self, item)
+    }
+
+    fn visit_impl_item(&mut self, item: &'tcx hir::ImplItem<'tcx>) {
+        let parent_id = self.tcx.hir().get_parent_item(item.hir_id);
+        let parent = self.tcx.hir().expect_item(parent_id);
+
+        let (kind, case) = match item.kind {
+            hir::ImplItemKind::Fn(..) => ("method", Case::Snake),
+            hir::ImplItemKind::Const(..) => ("associated const", Case::ScreamingSnake),
+            hir::ImplItemKind::TyAlias(..) => ("associated type", Case::Camel),
+        };
+        // Items in trait impls are named by the trait, so only look at
+        // inherent impls.
+        if let hir::ItemKind::Impl { of_trait: None, .. } = parent.kind {
+            self.check_name(item.ident, kind, case, item.span);
+            // Likewise their visibility, trait impl items have the trait's.
+            if !item.span.from_expansion() {
+                let visibility = visibility_name(&item.vis.node);
+                *self.stats.visibilities.entry(visibility.to_owned()).or_insert(0) += 1;
+            }
+        }
+        self.record_lifecycle(item.hir_id, kind, item.attrs);
+        self.record_doctests(item.attrs);
+        tracing::debug!(item = %self.item_path(item.hir_id), kind, "visiting impl item");
+
+        // Methods from derives (e.g., `clone(&self)`) would skew the
+        // receivers and accessor pairs, as with the per-function stats.
+        if let (hir::ImplItemKind::Fn(sig, _), false) = (&item.kind, item.span.from_expansion()) {
+            let self_ty = self
+                .tcx
+                .type_of(self.tcx.hir().local_def_id(parent_id).to_def_id());
+            let self_ty = self.type_name(self_ty);
+            self.record_receiver(self_ty.clone(), item.hir_id);
+            self.methods
+                .entry(self_ty)
+                .or_default()
+                .push((item.ident.to_string(), sig.decl.implicit_self));
+        }
+
+        intravisit::walk_impl_item(self, item)
+    }
+
+    fn visit_fn(
+        &mut self,
+        kind: FnKind<'tcx>,
+        decl: &'tcx hir::FnDecl<'tcx>,
+        body: hir::BodyId,
+        span: Span,
+        hir_id: hir::HirId,
+    ) {
+        // Closures are counted as part of the function they are in.
+        if let FnKind::Closure(_) = kind {
+            return intravisit::walk_fn(self, kind, decl, body, span, hir_id);
+        }
+
+        // Functions from macros, mostly derives (e.g., `clone` with its
+        // `#[inline]`, or `hash<__H>`), aren't the user's, so they would just
+        // skew the per-function stats.
+        if span.from_expansion() {
+            let outer_fn = self.current_fn.take();
+            intravisit::walk_fn(self, kind, decl, body, span, hir_id);
+            self.current_fn = outer_fn;
+            return;
+        }
+
+        let mut_ref_params = decl
+            .inputs
+            .iter()
+            .filter(|ty| {
+                matches!(
+                    ty.kind,
+                    hir::TyKind::Rptr(_, hir::MutTy { mutbl: hir::Mutability::Mut, .. })
+                )
+            })
+            .count();
+        let def_id = self.tcx.hir().local_def_id(hir_id).to_def_id();
+        let generics = self.tcx.generics_of(def_id);
+        if let Some(mono_items) = self.mono_items {
+            // Unlike `generic` below, this includes methods in generic impls
+            // and traits.
+            if generics.requires_monomorphization(self.tcx) && !mono_items.contains(&def_id) {
+                self.stats.uninstantiated_generics.push(self.item_path(hir_id));
+            }
+        }
+        let generics = generics.own_counts();
+        let abi = match kind {
+            FnKind::ItemFn(_, _, header, ..) => header.abi,
+            FnKind::Method(_, sig, ..) => sig.header.abi,
+            FnKind::Closure(_) => Abi::Rust,
+        };
+        if abi != Abi::Rust {
+            *self.stats.abi_fns.entry(abi.name().to_owned()).or_insert(0) += 1;
+        }
+
+        let mut raw_ptrs = RawPtrFinder::default();
+        intravisit::walk_fn_decl(&mut raw_ptrs, decl);
+        let body_expr = &self.tcx.hir().body(body).value;
+        let mut returns = ReturnFinder { last: last_return(body_expr), early: 0 };
+        returns.visit_expr(body_expr);
+        self.stats.functions.push(FnStats {
+            name: self.item_path(hir_id),
+            location: self.location(span),
+            const_ptrs: raw_ptrs.consts,
+            mut_ptrs: raw_ptrs.muts,
+            generic: generics.types > 0 || generics.consts > 0,
+            mut_ref_params,
+            inline: inline_attr(kind.attrs()),
+            stub: stub_kind(body_expr),
+            complexity: 1,
+            early_returns: returns.early,
+            churn: if self.options.with_churn { self.churn(span) } else { None },
+            ..FnStats::default()
+        });
+
+        let outer_fn = self.current_fn.replace(self.stats.functions.len() - 1);
+        let outer_len_check = mem::replace(&mut self.seen_len_check, false);
+        intravisit::walk_fn(self, kind, decl, body, span, hir_id);
+        self.current_fn = outer_fn;
+        self.seen_len_check = outer_len_check;
+    }
+
+    // Attributes on everything, including the crate (`#![allow(..)]`),
+    // statements, and expressions, come through here.
+    fn visit_attribute(&mut self, attr: &'tcx ast::Attribute) {
+        // Derives and other macros add their own `#[allow]`s.
+        if attr.span.from_expansion() {
+            return;
+        }
+
+        let level = attr.name_or_empty();
+        if !["allow", "expect", "warn", "deny", "forbid"].contains(&&*level.as_str()) {
+            return;
+        }
+        let lints = self.stats.lint_levels.entry(level.to_string()).or_default();
+        for lint in attr.meta_item_list().unwrap_or_default() {
+            if let Some(lint) = lint.meta_item() {
+                let name: Vec<_> = lint.path.segments.iter().map(|s| s.ident.to_string()).collect();
+                *lints.entry(name.join("::")).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn visit_foreign_item(&mut self, item: &'tcx hir::ForeignItem<'tcx>) {
+        self.stats.foreign_items += 1;
+        self.record_doctests(item.attrs);
+
+        intravisit::walk_foreign_item(self, item)
+    }
+
+    fn visit_ty(&mut self, ty: &'tcx hir::Ty<'tcx>) {
+        if let hir::TyKind::BareFn(bare_fn) = ty.kind {
+            if bare_fn.abi != Abi::Rust {
+                *self.stats.abi_fn_ptrs.entry(bare_fn.abi.name().to_owned()).or_insert(0) += 1;
+            }
+        }
+
+        intravisit::walk_ty(self, ty)
+    }
+
+    fn visit_nested_body(&mut self, id: hir::BodyId) {
+        let outer_results = self.typeck_results.replace(self.tcx.typeck_body(id));
+        self.visit_body(self.tcx.hir().body(id));
+        self.typeck_results = outer_results;
+    }
+
+    fn visit_stmt(&mut self, stmt: &'tcx hir::Stmt<'tcx>) {
+        if let Some(fn_stats) = self.current_fn() {
+            fn_stats.stmts += 1;
+        }
+
+        intravisit::walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+        if let Some(fn_stats) = self.current_fn() {
+            fn_stats.exprs += 1;
+        }
+
+        // Cyclomatic complexity: each `match` arm after the first (which
+        // includes `if`s, `while`s, and `for`s, since they are `match`es by
+        // now) and each `&&` or `||` is another path through the function.
+        // `?` isn't counted, it would swamp everything else.
+        let branches = match expr.kind {
+            hir::ExprKind::Match(_, _, hir::MatchSource::TryDesugar) => 0,
+            hir::ExprKind::Match(_, arms, _) => arms.len().saturating_sub(1),
+            hir::ExprKind::Binary(op, ..) => match op.node {
+                hir::BinOpKind::And | hir::BinOpKind::Or => 1,
+                _ => 0,
+            },
+            _ => 0,
+        };
+        if let Some(fn_stats) = self.current_fn() {
+            fn_stats.complexity += branches;
+        }
+
+        // Desugared loops (e.g., `for`) have `break`s of their own, but these
+        // come from an expansion so aren't counted.
+        if !expr.span.from_expansion() {
+            match expr.kind {
+                hir::ExprKind::Loop(_, Some(_), _) => self.stats.labeled_loops += 1,
+                hir::ExprKind::Block(_, Some(_)) => self.stats.labeled_blocks += 1,
+                hir::ExprKind::Break(destination, value) => {
+                    if destination.label.is_some() {
+                        self.stats.labeled_jumps += 1;
+                    }
+                    if value.is_some() {
+                        self.stats.break_values += 1;
+                    }
+                }
+                hir::ExprKind::Continue(destination) => {
+                    if destination.label.is_some() {
+                        self.stats.labeled_jumps += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match expr.kind {
+            // By the time we see the HIR, `if let` and `while let` (and
+            // others) have been desugared into `match`es. There is no
+            // `ExprKind::Let` in this compiler's HIR, but lowering records
+            // where each `match` came from, which is enough to tell them
+            // apart.
+            //
+            // Likewise `?`, we count the `match` it becomes, once per use.
+            // Its span is marked as a desugaring, so check it first.
+            hir::ExprKind::Match(_, _, hir::MatchSource::TryDesugar) => {
+                if let Some(fn_stats) = self.current_fn() {
+                    fn_stats.try_ops += 1;
+                }
+            }
+            // Macros (e.g., `format_args!` and `assert_eq!`) expand to
+            // `match`es too, but those aren't the user's choice.
+            hir::ExprKind::Match(..) if expr.span.from_expansion() => {}
+            hir::ExprKind::Match(_, _, hir::MatchSource::Normal) => self.stats.matches += 1,
+            hir::ExprKind::Match(_, _, hir::MatchSource::IfLetDesugar { .. }) => {
+                self.stats.if_lets += 1
+            }
+            hir::ExprKind::Match(_, _, hir::MatchSource::WhileLetDesugar) => {
+                self.stats.while_lets += 1
+            }
+            _ => {}
+        }
+
+        if self.options.call_graph {
+            // We only want calls within the crate.
+            let callee = self.callee(expr).filter(|def_id| def_id.is_local());
+            if let (Some(caller), Some(callee)) = (self.current_fn, callee) {
+                let caller = self.stats.functions[caller].name.clone();
+                let callee = self.tcx.def_path_str(callee);
+                self.stats.call_graph.insert((caller, callee));
+            }
+        }
+
+        if let (hir::ExprKind::MethodCall(_, _, args, ..), Some(typeck_results), false) =
+            (&expr.kind, self.typeck_results, expr.span.from_expansion())
+        {
+            // A call is dynamically dispatched if it calls a trait method and
+            // the receiver (after auto-deref, so `Box<dyn Trait>` and
+            // `&dyn Trait` both count) is a trait object. Calls from macros
+            // (e.g., `#[derive(Debug)]`'s `field`) aren't the user's.
+            let trait_method = typeck_results
+                .type_dependent_def_id(expr.hir_id)
+                .map_or(false, |def_id| self.tcx.trait_of_item(def_id).is_some());
+            let receiver = typeck_results.expr_ty_adjusted(&args[0]).peel_refs();
+            if trait_method && matches!(receiver.kind(), ty::Dynamic(..)) {
+                self.stats.dynamic_dispatch_calls += 1;
+            } else {
+                self.stats.static_dispatch_calls += 1;
+            }
+        }
+
+        // Literals from macros (e.g., in `assert_eq!`'s expansion) aren't the
+        // user's, and those outside function bodies (e.g., `const N: u32 =
+        // 42;`) are already named.
+        if let (hir::ExprKind::Lit(ref lit), Some(_)) = (&expr.kind, self.current_fn) {
+            let n = match lit.node {
+                ast::LitKind::Int(n, _) => Some((n.to_string(), n as f64)),
+                ast::LitKind::Float(n, _) => {
+                    let f = n.as_str().replace('_', "").parse().ok();
+                    f.map(|f| (n.to_string(), f))
+                }
+                _ => None,
+            };
+            if let Some((name, n)) = n {
+                if !expr.span.from_expansion() && !self.options.allows_magic_number(n) {
+                    *self.stats.magic_numbers.entry(name).or_insert(0) += 1;
+                    if let Some(fn_stats) = self.current_fn() {
+                        fn_stats.magic_numbers += 1;
+                    }
+                }
+            }
+        }
+
+        if let (hir::ExprKind::Path(ref qpath), Some(from), Some(typeck_results)) =
+            (&expr.kind, &self.current_const, self.typeck_results)
+        {
+            match typeck_results.qpath_res(qpath, expr.hir_id) {
+                Res::Def(DefKind::Const, def_id)
+                | Res::Def(DefKind::AssocConst, def_id)
+                | Res::Def(DefKind::Static, def_id) => {
+                    let to = self.tcx.def_path_str(def_id);
+                    self.stats.const_refs.insert((from.clone(), to));
+                }
+                _ => {}
+            }
+        }
+
+        // A `.len()` (or `.is_empty()`) call earlier in the function is taken
+        // as checking the bounds of any indexing after it, which is only a
+        // guess.
+        match expr.kind {
+            hir::ExprKind::MethodCall(segment, ..)
+                if ["len", "is_empty"].contains(&&*segment.ident.as_str()) =>
+            {
+                self.seen_len_check = true
+            }
+            hir::ExprKind::Index(_, index) if !expr.span.from_expansion() => {
+                self.record_index(expr, index)
+            }
+            _ => {}
+        }
+
+        if let hir::ExprKind::Closure(..) = expr.kind {
+            // A closure shares its type information with the function it is in,
+            // which is where upvar analysis records what it captures.
+            let def_id = self.tcx.hir().local_def_id(expr.hir_id).to_def_id();
+            let captures = self
+                .typeck_results
+                .and_then(|results| results.closure_captures.get(&def_id))
+                .map_or(0, |captures| captures.len());
+            *self.stats.closure_captures.entry(captures).or_insert(0) += 1;
+            self.stats
+                .largest_closures
+                .push((self.location(expr.span), captures));
+        }
+
+        intravisit::walk_expr(self, expr)
+    }
+
+    fn visit_pat(&mut self, pat: &'tcx hir::Pat<'tcx>) {
+        if let hir::PatKind::Binding(hir::BindingAnnotation::Mutable, ..) = pat.kind {
+            if let Some(fn_stats) = self.current_fn() {
+                fn_stats.mut_bindings += 1;
+            }
+        }
+
+        intravisit::walk_pat(self, pat)
+    }
+
+    fn visit_generic_param(&mut self, param: &'tcx hir::GenericParam<'tcx>) {
+        match param.kind {
+            hir::GenericParamKind::Const { .. } => self.stats.const_generic_params += 1,
+            // Elided lifetimes get 'fresh' parameters, we only want ones the
+            // user wrote.
+            hir::GenericParamKind::Lifetime { .. } => {
+                if let hir::ParamName::Plain(_) = param.name {
+                    self.stats.named_lifetime_params += 1;
+                }
+            }
+            // `?Sized` in a where clause has been moved on to the parameter
+            // by now.
+            hir::GenericParamKind::Type { .. } => {
+                let maybe_sized = param.bounds.iter().any(|bound| {
+                    matches!(bound, hir::GenericBound::Trait(_, hir::TraitBoundModifier::Maybe))
+                });
+                if maybe_sized {
+                    let owner = self.item_path(self.tcx.hir().get_parent_item(param.hir_id));
+                    *self.stats.maybe_sized_params.entry(owner).or_insert(0) += 1;
+                }
+            }
+        }
+
+        intravisit::walk_generic_param(self, param)
+    }
+
+    fn visit_lifetime(&mut self, lifetime: &'tcx hir::Lifetime) {
+        match lifetime.name {
+            hir::LifetimeName::Param(hir::ParamName::Plain(_)) | hir::LifetimeName::Static => {
+                self.stats.named_lifetime_uses += 1
+            }
+            hir::LifetimeName::Underscore => self.stats.anonymous_lifetimes += 1,
+            // In impl headers, `'_` (like an elided lifetime) is lowered to a
+            // fresh lifetime parameter of the impl, so we can only tell it was
+            // written from the source.
+            hir::LifetimeName::Param(hir::ParamName::Fresh(_)) => {
+                let snippet = self.tcx.sess.source_map().span_to_snippet(lifetime.span);
+                if snippet.map_or(false, |snippet| snippet == "'_") {
+                    self.stats.anonymous_lifetimes += 1;
+                }
+            }
+            _ => {}
+        }
+
+        intravisit::walk_lifetime(self, lifetime)
+    }
+
+    fn visit_param_bound(&mut self, bound: &'tcx hir::GenericBound<'tcx>) {
+        let in_bound = self.in_bound;
+        self.in_bound = true;
+        intravisit::walk_param_bound(self, bound);
+        self.in_bound = in_bound;
+    }
+
+    fn visit_generic_args(&mut self, path_span: Span, args: &'tcx hir::GenericArgs<'tcx>) {
+        let in_parenthesized = mem::replace(&mut self.in_parenthesized, args.parenthesized);
+        intravisit::walk_generic_args(self, path_span, args);
+        self.in_parenthesized = in_parenthesized;
+    }
+
+    // Only bindings in bounds (inline or in where clauses) count, not those in
+    // types like `dyn Iterator<Item = u8>`. Nor do the `Output = ..` bindings
+    // which lowering gives every `Fn(..)` bound, even `F: FnOnce()`.
+    fn visit_assoc_type_binding(&mut self, binding: &'tcx hir::TypeBinding<'tcx>) {
+        if let hir::TypeBindingKind::Equality { .. } = binding.kind {
+            if self.in_bound && !self.in_parenthesized {
+                self.stats.assoc_type_equality_bounds += 1;
+            }
+        }
+
+        intravisit::walk_assoc_type_binding(self, binding)
+    }
+
+    // Lowering has already worked out which ambiguous arguments (like `N` in
+    // `Foo<N>`) are really consts, so we only need to check the kind.
+    fn visit_generic_arg(&mut self, arg: &'tcx hir::GenericArg<'tcx>) {
+        match arg {
+            hir::GenericArg::Lifetime(lt) => self.visit_lifetime(lt),
+            hir::GenericArg::Type(ty) => self.visit_ty(ty),
+            hir::GenericArg::Const(ct) => {
+                self.stats.const_generic_args += 1;
+                self.visit_anon_const(&ct.value)
+            }
+        }
+    }
+}
+
+// Counts the raw pointer types in a function's signature, including those
+// nested inside other types, e.g., `Option<*mut T>`.
+#[derive(Default)]
+struct RawPtrFinder {
+    consts: usize,
+    muts: usize,
+}
+
+impl<'tcx> Visitor<'tcx> for RawPtrFinder {
+    type Map = Map<'tcx>;
+
+    fn nested_visit_map(&mut self) -> NestedVisitorMap<Self::Map> {
+        NestedVisitorMap::None
+    }
+
+    fn visit_ty(&mut self, ty: &'tcx hir::Ty<'tcx>) {
+        match ty.kind {
+            hir::TyKind::Ptr(hir::MutTy { mutbl: hir::Mutability::Not, .. }) => self.consts += 1,
+            hir::TyKind::Ptr(hir::MutTy { mutbl: hir::Mutability::Mut, .. }) => self.muts += 1,
+            _ => {}
+        }
+
+        intravisit::walk_ty(self, ty)
+    }
+}
+
+// Counts the early returns in a function's body, i.e., the `return`s other
+// than `last`. Closures are bodies of their own, so aren't looked in.
+struct ReturnFinder {
+    last: Option<hir::HirId>,
+    early: usize,
+}
+
+impl<'tcx> Visitor<'tcx> for ReturnFinder {
+    type Map = Map<'tcx>;
+
+    fn nested_visit_map(&mut self) -> NestedVisitorMap<Self::Map> {
+        NestedVisitorMap::None
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+        // `?` is desugared to a `match` with a `return`.
+        if let hir::ExprKind::Ret(_) = expr.kind {
+            if !expr.span.from_expansion() && Some(expr.hir_id) != self.last {
+                self.early += 1;
+            }
+        }
+
+        intravisit::walk_expr(self, expr)
+    }
+}
+
+// The `return` which ends a function's body, if there is one, i.e., `return x`
+// or `return x;` as the last thing in the body.
+fn last_return(body: &hir::Expr<'_>) -> Option<hir::HirId> {
+    let block = match body.kind {
+        hir::ExprKind::Block(block, _) => block,
+        _ => return None,
+    };
+    let last = match (block.expr, block.stmts.last()) {
+        (Some(expr), _) => expr,
+        (None, Some(&hir::Stmt { kind: hir::StmtKind::Semi(expr), .. })) => expr,
+        _ => return None,
+    };
+    match last.kind {
+        hir::ExprKind::Ret(_) => Some(last.hir_id),
+        _ => None,
+    }
+}
+
+fn inline_attr(attrs: &[ast::Attribute]) -> Inline {
+    let attr = match attrs.iter().find(|attr| attr.has_name(sym::inline)) {
+        Some(attr) => attr,
+        None => return Inline::None,
+    };
+    match attr.meta_item_list() {
+        Some(list) if list.iter().any(|item| item.has_name(sym::always)) => Inline::Always,
+        Some(list) if list.iter().any(|item| item.has_name(sym::never)) => Inline::Never,
+        _ => Inline::Hint,
+    }
+}
+
+fn stub_kind(body: &hir::Expr<'_>) -> Option<Stub> {
+    let block = match body.kind {
+        hir::ExprKind::Block(block, _) => block,
+        _ => return None,
+    };
+    let expr = match (block.stmts, block.expr) {
+        ([], None) => return Some(Stub::Empty),
+        ([], Some(expr)) => expr,
+        ([stmt], None) => match stmt.kind {
+            hir::StmtKind::Semi(expr) | hir::StmtKind::Expr(expr) => expr,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    // Macros have been expanded by now, so we look for `todo!` or
+    // `unimplemented!` among the macros the expression came from.
+    expr.span.macro_backtrace().find_map(|expn_data| match expn_data.kind {
+        ExpnKind::Macro(MacroKind::Bang, name) => match &*name.as_str() {
+            "todo" => Some(Stub::Todo),
+            "unimplemented" => Some(Stub::Unimplemented),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+// Well-known paths and the short names they are usually written as.
+const SHORT_PATHS: &[(&str, &str)] = &[
+    ("std::boxed::Box", "Box"),
+    ("std::cell::Cell", "Cell"),
+    ("std::cell::RefCell", "RefCell"),
+    ("std::collections::BTreeMap", "BTreeMap"),
+    ("std::collections::BTreeSet", "BTreeSet"),
+    ("std::collections::HashMap", "HashMap"),
+    ("std::collections::HashSet", "HashSet"),
+    ("std::collections::VecDeque", "VecDeque"),
+    ("std::marker::PhantomData", "PhantomData"),
+    ("std::option::Option", "Option"),
+    ("std::pin::Pin", "Pin"),
+    ("std::rc::Rc", "Rc"),
+    ("std::result::Result", "Result"),
+    ("std::string::String", "String"),
+    ("std::sync::Arc", "Arc"),
+    ("std::vec::Vec", "Vec"),
+];
+
+/// Abbreviates well-known std paths in a type (or anything else printed by
+/// the compiler), e.g., `std::vec::Vec<std::string::String>` becomes
+/// `Vec<String>`. Paths through `core` and `alloc` are treated like those
+/// through `std`.
+pub fn abbreviate_paths(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while !rest.is_empty() {
+        // Split off the next path (identifiers and `::`) or other character.
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .unwrap_or_else(|| rest.len());
+        if end == 0 {
+            let c = rest.chars().next().unwrap();
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let path = &rest[..end];
+        let std_path = ["core::", "alloc::"]
+            .iter()
+            .find_map(|prefix| path.strip_prefix(prefix))
+            .map(|tail| format!("std::{}", tail))
+            .unwrap_or_else(|| path.to_owned());
+        match SHORT_PATHS.iter().find(|(long, _)| *long == std_path) {
+            Some((_, short)) => result.push_str(short),
+            None => result.push_str(path),
+        }
+        rest = &rest[end..];
+    }
+    result
+}
+
+// Strips the `crate::` prefix (if any) from a path, so paths from users and
+// from the compiler compare equal. The crate root module becomes "".
+fn crate_relative(path: &str) -> &str {
+    if path == "crate" {
+        return "";
+    }
+    path.strip_prefix("crate::").unwrap_or(path)
+}
+
+// Is `def_id` `Box`, `Rc`, `Arc`, or `Pin`?
+fn is_smart_pointer(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    match &*tcx.def_path_str(def_id) {
+        "std::boxed::Box" | "std::rc::Rc" | "std::sync::Arc" | "std::pin::Pin" => true,
+        "alloc::boxed::Box" | "alloc::rc::Rc" | "alloc::sync::Arc" | "core::pin::Pin" => true,
+        _ => false,
+    }
+}
+
+// Is `ty` just a type parameter, like `T`?
+fn is_type_param(ty: &hir::Ty<'_>) -> bool {
+    match ty.kind {
+        hir::TyKind::Path(hir::QPath::Resolved(None, path)) => {
+            matches!(path.res, Res::Def(DefKind::TyParam, _))
+        }
+        _ => false,
+    }
+}
+
+// The type parameters in `generics` with a `Sized` bound written on the
+// parameter or in the where clause.
+fn sized_params(tcx: TyCtxt<'_>, generics: &hir::Generics<'_>) -> Vec<Symbol> {
+    let sized_trait = tcx.lang_items().sized_trait();
+    let is_sized = |bounds: &[hir::GenericBound<'_>]| {
+        bounds.iter().any(|bound| match bound {
+            hir::GenericBound::Trait(poly_trait_ref, hir::TraitBoundModifier::None) => {
+                poly_trait_ref.trait_ref.trait_def_id() == sized_trait
+            }
+            _ => false,
+        })
+    };
+
+    let mut params: Vec<_> = generics
+        .params
+        .iter()
+        .filter(|param| is_sized(param.bounds))
+        .map(|param| param.name.ident().name)
+        .collect();
+    for predicate in generics.where_clause.predicates {
+        if let hir::WherePredicate::BoundPredicate(predicate) = predicate {
+            if let hir::TyKind::Path(hir::QPath::Resolved(None, path)) = predicate.bounded_ty.kind {
+                if is_type_param(predicate.bounded_ty) && is_sized(predicate.bounds) {
+                    params.push(path.segments[0].ident.name);
+                }
+            }
+        }
+    }
+    params
+}
+
+fn visibility_name(vis: &hir::VisibilityKind<'_>) -> &'static str {
+    match vis {
+        hir::VisibilityKind::Public => "pub",
+        hir::VisibilityKind::Crate(_) => "pub(crate)",
+        // `pub(self)` is the same as private, and `pub(in crate)` as
+        // `pub(crate)`.
+        hir::VisibilityKind::Restricted { path, .. } => match path.segments {
+            [segment] if segment.ident.name == kw::Super => "pub(super)",
+            [segment] if segment.ident.name == kw::SelfLower => "private",
+            [segment] if segment.ident.name == kw::Crate => "pub(crate)",
+            _ => "pub(in path)",
+        },
+        hir::VisibilityKind::Inherited => "private",
+    }
+}
+
+fn item_kind_name(kind: &hir::ItemKind<'_>) -> &'static str {
+    match kind {
+        hir::ItemKind::ExternCrate(..) => "extern crate",
+        hir::ItemKind::Use(..) => "use",
+        hir::ItemKind::Static(..) => "static",
+        hir::ItemKind::Const(..) => "const",
+        hir::ItemKind::Fn(..) => "function",
+        hir::ItemKind::Mod(..) => "module",
+        hir::ItemKind::ForeignMod(..) => "extern block",
+        hir::ItemKind::GlobalAsm(..) => "global asm",
+        hir::ItemKind::TyAlias(..) => "type alias",
+        hir::ItemKind::OpaqueTy(..) => "opaque type",
+        hir::ItemKind::Enum(..) => "enum",
+        hir::ItemKind::Struct(..) => "struct",
+        hir::ItemKind::Union(..) => "union",
+        hir::ItemKind::Trait(..) => "trait",
+        hir::ItemKind::TraitAlias(..) => "trait alias",
+        hir::ItemKind::Impl { .. } => "impl",
+    }
+}
+
+// Naming conventions, as checked by rustc's `non_snake_case`,
+// `non_camel_case_types`, and `non_upper_case_globals` lints (though our checks
+// are much simpler).
+#[derive(Clone, Copy)]
+enum Case {
+    Snake,
+    Camel,
+    ScreamingSnake,
+}
+
+impl Case {
+    fn name(self) -> &'static str {
+        match self {
+            Case::Snake => "snake_case",
+            Case::Camel => "CamelCase",
+            Case::ScreamingSnake => "SCREAMING_SNAKE_CASE",
+        }
+    }
+
+    fn matches(self, name: &str) -> bool {
+        // Leading and trailing underscores are allowed in any case.
+        let name = name.trim_matches('_');
+        match self {
+            Case::Snake => !name.chars().any(char::is_uppercase),
+            Case::Camel => {
+                name.chars().next().map_or(true, char::is_uppercase) && !name.contains('_')
+            }
+            Case::ScreamingSnake => !name.chars().any(char::is_lowercase),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Finding the examples in doc comments, for `--doctests`. We follow rustdoc's
+// rules (roughly): a fenced code block is Rust unless its info string says it
+// is something else, `#` lines are hidden from readers but still compiled,
+// and code without a `main` is wrapped in one.
+
+// Info string words which rustdoc understands, any other word (e.g., `text`)
+// means the block isn't Rust.
+const RUST_ATTRIBUTES: &[&str] =
+    &["rust", "no_run", "should_panic", "ignore", "compile_fail", "allow_fail", "test_harness"];
+
+// Returns the source of each doctest in `doc` which should compile, ready to
+// be analysed as a crate of its own. Blocks marked `ignore` or `compile_fail`
+// are left out, since they likely won't compile; `no_run` and `should_panic`
+// blocks compile fine.
+pub(crate) fn extract(doc: &str) -> Vec<String> {
+    let mut doctests = vec![];
+    // The fence which opened the current block and whether we want it.
+    let mut block: Option<(&str, bool)> = None;
+    let mut code = vec![];
+
+    for line in doc.lines() {
+        // Doc comments usually start with a space, i.e., `/// foo`.
+        let line = line.strip_prefix(' ').unwrap_or(line);
+        let trimmed = line.trim_start();
+
+        match block {
+            None => {
+                let fence = fence(trimmed);
+                if !fence.is_empty() {
+                    block = Some((fence, is_rust(&trimmed[fence.len()..])));
+                }
+            }
+            Some((open, wanted)) => {
+                if trimmed.starts_with(open) && trimmed[open.len()..].trim().is_empty() {
+                    if wanted {
+                        doctests.push(wrap(&code));
+                    }
+                    block = None;
+                    code.clear();
+                } else if wanted {
+                    code.push(unhide(line));
+                }
+            }
+        }
+    }
+
+    doctests
+}
+
+// The fence (three or more backticks or tildes) at the start of `line`, or ""
+// if there isn't one.
+fn fence(line: &str) -> &str {
+    for &c in &['`', '~'] {
+        let len = line.len() - line.trim_start_matches(c).len();
+        if len >= 3 {
+            return &line[..len];
+        }
+    }
+    ""
+}
+
+fn is_rust(info: &str) -> bool {
+    info.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .all(|word| {
+            if word == "ignore" || word.starts_with("ignore-") || word == "compile_fail" {
+                return false;
+            }
+            RUST_ATTRIBUTES.contains(&word)
+                || word.starts_with("edition")
+                || (word.starts_with('E') && word[1..].chars().all(|c| c.is_ascii_digit()))
+        })
+}
+
+// Strips the `# ` from a hidden line, and turns `##` back into `#`.
+fn unhide(line: &str) -> &str {
+    let trimmed = line.trim_start();
+    if trimmed == "#" {
+        ""
+    } else if let Some(rest) = trimmed.strip_prefix("# ") {
+        rest
+    } else if trimmed.starts_with("##") {
+        &trimmed[1..]
+    } else {
+        line
+    }
+}
+
+// Makes a crate from the lines of a doctest, wrapping them in `fn main` (as
+// rustdoc does) unless they already have one. Crate attributes have to stay
+// at the top.
+fn wrap(code: &[&str]) -> String {
+    let (attrs, body): (Vec<&str>, Vec<&str>) =
+        code.iter().copied().partition(|line| line.trim_start().starts_with("#!["));
+    let mut source = attrs.join("\n");
+    source.push('\n');
+    if body.iter().any(|line| line.contains("fn main")) {
+        source.push_str(&body.join("\n"));
+    } else {
+        source.push_str("fn main() {\n");
+        source.push_str(&body.join("\n"));
+        source.push_str("\n}");
+    }
+    source.push('\n');
+    source
+}
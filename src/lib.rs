@@ -0,0 +1,42 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The parts of stupid-stats which don't depend on being run as rustc. The
+// `stupid` binary uses these from its callbacks, other tools can use
+// `analyze` or `analyze_str` to get stats for source they already have.
+#![feature(rustc_private)]
+
+extern crate rustc_ast;
+extern crate rustc_driver;
+extern crate rustc_errors;
+extern crate rustc_hash;
+extern crate rustc_hir;
+extern crate rustc_interface;
+extern crate rustc_middle;
+extern crate rustc_session;
+extern crate rustc_span;
+extern crate rustc_target;
+
+mod analyze;
+pub mod batch;
+mod churn;
+pub mod collect;
+mod doctest;
+pub mod options;
+pub mod report;
+pub mod stats;
+
+pub use analyze::{analyze, analyze_str};
+
+/// The sysroot of the toolchain we were built with, the compiler we link
+/// against needs the standard library from the same toolchain.
+pub fn sysroot() -> String {
+    let home = option_env!("RUSTUP_HOME");
+    let toolchain = option_env!("RUSTUP_TOOLCHAIN");
+    format!("{}/toolchains/{}", home.unwrap(), toolchain.unwrap())
+}
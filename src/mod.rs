@@ -15,19 +15,38 @@ extern crate rustc_hir;
 extern crate rustc_ast;
 extern crate rustc_ast_pretty;
 extern crate rustc_attr;
+extern crate rustc_middle;
 
 use rustc_driver::{Compilation, Callbacks};
 use rustc_interface::{Config, Queries, interface::Compiler};
 use rustc_ast::{ast, visit};
 use rustc_ast_pretty::pprust;
+use rustc_middle::ty::{Ty, TyCtxt};
+
+use stupid_stats::options::Options;
+use stupid_stats::stats::Stats;
+use stupid_stats::{batch, collect, report};
+
+use termcolor::{NoColor, StandardStream};
+use tracing::Level;
+
+use std::fs::{self, File};
+use std::io::{self, BufRead};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 // This is the highest level controller of compiler execution. We often want
 // some context to remember facts about compilation (e.g., the input file or
-// some processed flags), but for this simple example, we don't need anything.
+// some processed flags), here we just need our own command line options.
 // We need to delegate to RustcDefaultCalls when we want to do what the rust
 // compiler would do in certain circumstances. We do this so that we can emit
 // some of the same info to Cargo.
-struct StupidCalls;
+struct StupidCalls {
+    options: Options,
+    // Number of warnings the compiler emitted, filled in after analysis.
+    warnings: usize,
+}
 
 // Callbacks is a trait for running code during compilation at the driver level. It
 // is basically a set of callbacks to call at various stages of compilation to
@@ -70,14 +89,30 @@ impl Callbacks for StupidCalls {
     ) -> Compilation {
 	let qr = &mut *queries.global_ctxt().unwrap().peek_mut();
 	qr.enter(|tcx| {
+            if let Some(name) = &self.options.dump_hir {
+                dump_hir(tcx, name);
+            }
+
+            let full_paths = self.options.full_paths;
+            let type_name = |ty: Ty<'_>| {
+                let ty = format!("{:?}", ty);
+                if full_paths {
+                    ty
+                } else {
+                    collect::abbreviate_paths(&ty)
+                }
+            };
+            let mut signatures = vec![];
 	    for (_, item) in &tcx.hir().krate().items {
-                //println!("{:?}", item.kind);
-                //println!("--------------------------------------------");
+                if !collect::in_module_path(tcx, &self.options, item) {
+                    continue;
+                }
+                tracing::debug!(kind = ?item.kind, "found item");
                 match item.kind {
                     rustc_hir::ItemKind::Fn(_, _, _) => {
                         let name = item.ident;
                         let ty = tcx.type_of(tcx.hir().local_def_id(item.hir_id));
-                        println!("{:?}:\t{:?}", name, ty);
+                        signatures.push((format!("{:?}", name), type_name(ty)));
                     },
 		    rustc_hir::ItemKind::Impl{
 			unsafety: _,
@@ -92,10 +127,10 @@ impl Callbacks for StupidCalls {
 		    } => {
 			for itm in itms {
 			    match itm.kind {
-				rustc_hir::AssocItemKind::Fn {has_self: has_self} => {
-				    let name = item.ident;
+				rustc_hir::AssocItemKind::Fn { .. } => {
+				    let name = itm.ident;
 				    let ty = tcx.type_of(tcx.hir().local_def_id(itm.id.hir_id));
-				    println!("{:?}:\t{:?}", name, ty);
+				    signatures.push((format!("{:?}", name), type_name(ty)));
 				},
 				_ => {},
 			    }
@@ -104,12 +139,41 @@ impl Callbacks for StupidCalls {
                     _ => (),
                 }
             }
+
+            // Walk the HIR collecting our stats, then print them.
+            let mut stats = collect::collect(tcx, &self.options);
+            stats.signatures = signatures;
+            stats.sort();
+            report::write(&stats, &self.options, &mut stdout(&self.options)).unwrap();
+            self.warnings = stats.warnings;
 	});
 
         Compilation::Continue
     }
 }
 
+// Prints the HIR for every item, impl item, or trait item called `name`. This
+// is what our stats are collected from, so it is handy for seeing why we get
+// the numbers we do.
+fn dump_hir(tcx: TyCtxt<'_>, name: &str) {
+    let krate = tcx.hir().krate();
+    for item in krate.items.values() {
+        if item.ident.as_str() == name {
+            println!("{:#?}", item);
+        }
+    }
+    for item in krate.impl_items.values() {
+        if item.ident.as_str() == name {
+            println!("{:#?}", item);
+        }
+    }
+    for item in krate.trait_items.values() {
+        if item.ident.as_str() == name {
+            println!("{:#?}", item);
+        }
+    }
+}
+
 // We'll collect our stats by walking the AST. To do that we need a visitor object.
 struct StupidVisitor {
     // The count of prinlns.
@@ -194,21 +258,204 @@ impl<'a> visit::Visitor<'a> for StupidVisitor {
 
 /// Adds the correct --sysroot option.
 fn sys_root() -> Vec<String> {
-    let home = option_env!("RUSTUP_HOME");
-    let toolchain = option_env!("RUSTUP_TOOLCHAIN");
-    let sysroot = format!("{}/toolchains/{}", home.unwrap(), toolchain.unwrap());
-    vec!["--sysroot".into(), sysroot]
+    vec!["--sysroot".into(), stupid_stats::sysroot()]
 }
 
 fn main() {
+    // Grab the command line arguments.
+    let args: Vec<_> = std::env::args_os().flat_map(|s| s.into_string()).collect();
+    // Take out our own options, everything else goes to rustc.
+    let (options, args) = Options::from_args(args).unwrap_or_else(|msg| error(&msg));
+    init_logging(options.verbose);
+
+    if options.version_info {
+        return print_version_info();
+    }
+
+    if options.repl {
+        return run_repl(&options);
+    }
+
+    // If we're given more than one file (or a directory) then we can't act as
+    // rustc, instead we analyse each file ourselves.
+    let inputs = batch::inputs(&args);
+    if options.merge {
+        return run_merge(&inputs, &options);
+    }
+    // `--output-dir` needs the stats for each file, so goes through batch
+    // mode even for a single file.
+    if batch::is_batch(&inputs) || options.output_dir.is_some() {
+        return run_batch(&inputs, &options);
+    }
+
+    let mut calls = StupidCalls { options, warnings: 0 };
     let _ = rustc_driver::catch_fatal_errors(|| {
-        // Grab the command line arguments.
-        let args: Vec<_> = std::env::args_os().flat_map(|s| s.into_string()).collect();
         let args2 = args.iter()
             .map(|s| (*s).to_string())
             .chain(sys_root().into_iter())
             .collect::<Vec<_>>();
 
-        rustc_driver::run_compiler(&args2, &mut StupidCalls, None, None)
+        rustc_driver::run_compiler(&args2, &mut calls, None, None)
     }).map_err(|e| println!("{:?}", e));
+
+    check_warnings(&calls.options, calls.warnings);
+}
+
+// Logs to stderr, so stdout is just the stats. Without `-v` only warnings
+// (of ours, the compiler's own are separate) are shown.
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        _ => Level::DEBUG,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .init();
+}
+
+// Implements `--version-info`. We only work with the compiler we were built
+// against, so this is the first thing to check when something goes wrong.
+fn print_version_info() {
+    let sysroot = stupid_stats::sysroot();
+    println!("stupid-stats {}", env!("CARGO_PKG_VERSION"));
+    println!("sysroot: {}", sysroot);
+
+    let rustc = PathBuf::from(&sysroot).join("bin").join("rustc");
+    match Command::new(&rustc).args(&["--version", "--verbose"]).output() {
+        Ok(output) if output.status.success() => {
+            print!("{}", String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => error(&format!(
+            "`{} --version --verbose` failed: {}",
+            rustc.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => error(&format!("couldn't run `{}`: {}", rustc.display(), e)),
+    }
+}
+
+fn run_batch(inputs: &[PathBuf], options: &Options) {
+    let sources = batch::source_files(inputs, options).unwrap_or_else(|msg| error(&msg));
+    if sources.excluded > 0 {
+        eprintln!("Excluded {} files matching `--exclude-glob`", sources.excluded);
+    }
+    for (file, size) in &sources.too_big {
+        eprintln!("Skipped `{}`, {} bytes is over `--max-file-size`", file.display(), size);
+    }
+
+    let total = sources.files.len();
+    let files = batch::sample(sources.files, options);
+    let each = batch::analyze_each(&files, options).unwrap_or_else(|file| {
+        error(&format!("couldn't analyse `{}`, stopping (`--fail-fast`)", file.display()))
+    });
+    let mut stats = Stats::default();
+    let mut written = vec![];
+    for (file, file_stats) in each {
+        if let Some(dir) = &options.output_dir {
+            written.push(write_output(dir, &file, &file_stats, options));
+        }
+        stats.merge(file_stats);
+    }
+    if options.sample_rate.is_some() {
+        stats.extrapolate(files.len(), total);
+    }
+    report::write(&stats, options, &mut stdout(options)).unwrap();
+
+    // The manifest goes to stderr like our other notes, so stdout is still
+    // just the stats.
+    if let Some(dir) = &options.output_dir {
+        eprintln!("Wrote {} files to `{}`:", written.len(), dir.display());
+        for path in &written {
+            eprintln!("    {}", path.display());
+        }
+    }
+    check_warnings(options, stats.warnings);
+}
+
+// Implements `--output-dir` for one file, returning the path written to.
+fn write_output(dir: &Path, file: &Path, stats: &Stats, options: &Options) -> PathBuf {
+    let path = batch::output_path(dir, file, options.format);
+    // A file is never a terminal, so its JSON shouldn't depend on whether
+    // stdout is.
+    let options =
+        &Options { json_compact: Some(options.json_compact.unwrap_or(true)), ..options.clone() };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| File::create(&path))
+        .and_then(|out| report::write(stats, options, &mut NoColor::new(out)));
+    if let Err(e) = result {
+        error(&format!("couldn't write `{}`: {}", path.display(), e));
+    }
+    path
+}
+
+// Implements `--merge`, combining stats from earlier runs with `--format json`,
+// e.g., on different machines.
+fn run_merge(inputs: &[PathBuf], options: &Options) {
+    if inputs.is_empty() {
+        error("`--merge` needs some files to merge");
+    }
+
+    let mut stats = Stats::default();
+    for input in inputs {
+        let name = input.display().to_string();
+        let mut file = File::open(input)
+            .unwrap_or_else(|e| error(&format!("couldn't open `{}`: {}", name, e)));
+        stats.merge(report::read_json(&name, &mut file).unwrap_or_else(|msg| error(&msg)));
+    }
+
+    report::write(&stats, options, &mut stdout(options)).unwrap();
+    check_warnings(options, stats.warnings);
+}
+
+// Implements `--fail-on-warnings`.
+fn check_warnings(options: &Options, warnings: usize) {
+    if options.fail_on_warnings && warnings > 0 {
+        error(&format!("compiler emitted {} warnings", warnings));
+    }
+}
+
+// Reads snippets from stdin, each ended by a blank line, and prints the stats
+// for each one. A snippet is compiled as a library crate of its own.
+fn run_repl(options: &Options) {
+    eprintln!("Enter Rust code, ending each snippet with a blank line; Ctrl-D to quit.");
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut snippet = String::new();
+    let mut done = false;
+    while !done {
+        match lines.next() {
+            Some(Ok(line)) if !line.trim().is_empty() => {
+                snippet.push_str(&line);
+                snippet.push('\n');
+                continue;
+            }
+            Some(Ok(_)) => {}
+            Some(Err(_)) | None => done = true,
+        }
+        if snippet.trim().is_empty() {
+            continue;
+        }
+
+        match stupid_stats::analyze_str("<repl>", mem::take(&mut snippet), options) {
+            Ok(stats) => report::write(&stats, options, &mut stdout(options)).unwrap(),
+            // rustc will already have explained what was wrong, so just carry
+            // on with the next snippet.
+            Err(_) => eprintln!("error: couldn't analyse snippet"),
+        }
+    }
+}
+
+// Where we write the stats, coloured according to `--color`.
+fn stdout(options: &Options) -> StandardStream {
+    StandardStream::stdout(report::color_choice(options))
+}
+
+fn error(msg: &str) -> ! {
+    eprintln!("error: {}", msg);
+    std::process::exit(1);
 }
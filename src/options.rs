@@ -0,0 +1,459 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rustc_span::edition::{Edition, ALL_EDITIONS};
+use serde::Deserialize;
+
+/// Options which control stupid-stats itself, rather than the compiler.
+///
+/// Options come from three places, in order of precedence: the command line,
+/// a config file given with `--config FILE`, and finally the defaults. The
+/// config file is TOML, with keys named like the command line options, e.g.,
+/// `format = "json"` or `cfg = ["unix"]`. Only defaults can be set there: the
+/// format, thresholds, filters, edition, cfgs, and externs. Switches and modes
+/// (e.g., `--repl`) are command line only, since they couldn't be turned off
+/// again, and any other key is an error.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    /// `--group-by-file`: also report counts for each source file.
+    #[serde(skip)]
+    pub group_by_file: bool,
+    /// `--dump-hir NAME`: print the HIR of any item called `NAME`.
+    #[serde(skip)]
+    pub dump_hir: Option<String>,
+    /// `--check-naming`: report names which break the naming conventions.
+    #[serde(skip)]
+    pub check_naming: bool,
+    /// `--edition EDITION`, this is shared with rustc.
+    pub edition: Option<String>,
+    /// `--cfg SPEC`, this is shared with rustc. If any are given on the
+    /// command line, those in the config file are ignored.
+    #[serde(rename = "cfg")]
+    pub cfgs: Vec<String>,
+    /// `--extern NAME=PATH`, this is shared with rustc. As with `--cfg`, any on
+    /// the command line replace those in the config file.
+    #[serde(rename = "extern")]
+    pub externs: Vec<String>,
+    /// `-L [KIND=]PATH`, this is shared with rustc. Where to look for the
+    /// crates named by `--extern NAME` and for their dependencies. As with
+    /// `--cfg`, any on the command line replace those in the config file.
+    #[serde(rename = "lib-path")]
+    pub lib_paths: Vec<String>,
+    /// `--exclude-glob PATTERN`: when analysing several files, skip those
+    /// matching `PATTERN`. Patterns are matched against paths as they are
+    /// found, e.g., `src/gen/foo.pb.rs`. Can be given more than once.
+    #[serde(rename = "exclude-glob")]
+    pub exclude_globs: Vec<String>,
+    /// `--jobs N`: when analysing several files, analyse at most `N` at once.
+    /// Defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// `--max-file-size BYTES`: when analysing several files, skip any bigger
+    /// than `BYTES`.
+    pub max_file_size: Option<u64>,
+    /// `--repl`: read snippets of code from stdin and print stats for each.
+    #[serde(skip)]
+    pub repl: bool,
+    /// `--module-path PATH`: only collect stats for items inside the module
+    /// `PATH` (e.g., `crate::foo::bar`) and its submodules.
+    pub module_path: Option<String>,
+    /// `--fail-on-warnings`: exit with an error if the compiler emitted any
+    /// warnings.
+    #[serde(skip)]
+    pub fail_on_warnings: bool,
+    /// `--format FORMAT`: how to print the stats.
+    pub format: Format,
+    /// `--call-graph`: collect which functions call which.
+    #[serde(skip)]
+    pub call_graph: bool,
+    /// `--stmt-ratio`: report the ratio of statements to expressions for each
+    /// function.
+    #[serde(skip)]
+    pub stmt_ratio: bool,
+    /// `--magic-number-allow 0,1,2`: numeric literals which aren't counted as
+    /// magic numbers. Defaults to 0 and 1.
+    pub magic_number_allow: Option<Vec<f64>>,
+    /// `--fail-fast`: when analysing several files, stop at the first which
+    /// fails. `--keep-going`, the default, analyses the rest anyway.
+    #[serde(skip)]
+    pub fail_fast: bool,
+    /// `--version-info`: print our version, the sysroot, and the version of
+    /// rustc in it, then exit.
+    #[serde(skip)]
+    pub version_info: bool,
+    /// `--with-churn`: ask git how often each function has changed, and
+    /// combine that with its complexity to suggest what to refactor first.
+    /// This needs a git repository and is slow.
+    #[serde(skip)]
+    pub with_churn: bool,
+    /// `--sample-rate RATE`: when analysing several files, only analyse a
+    /// random fraction `RATE` of them (e.g., `0.1` for 10%) and estimate the
+    /// totals from those.
+    #[serde(skip)]
+    pub sample_rate: Option<f64>,
+    /// `--seed N`: seed for `--sample-rate`, so the same files are picked
+    /// each time.
+    #[serde(skip)]
+    pub seed: Option<u64>,
+    /// `--merge`: rather than analysing anything, read the inputs as stats
+    /// written by `--format json` and combine them.
+    #[serde(skip)]
+    pub merge: bool,
+    /// `--uninstantiated-generics`: find generic functions which are never
+    /// instantiated. This runs monomorphization, so is slower.
+    #[serde(skip)]
+    pub uninstantiated_generics: bool,
+    /// `--color WHEN`: whether to colour text output, this is shared with
+    /// rustc.
+    pub color: ColorMode,
+    /// `--explain`: with text output, say what each stat counts and any
+    /// caveats. This hides rustc's `--explain CODE`.
+    #[serde(skip)]
+    pub explain: bool,
+    /// `--full-paths`: print types with full paths, e.g.,
+    /// `std::vec::Vec<std::string::String>` rather than `Vec<String>`.
+    #[serde(skip)]
+    pub full_paths: bool,
+    /// `--doctests`: also analyse the code blocks in doc comments, each as a
+    /// crate of its own, and report their stats separately.
+    #[serde(skip)]
+    pub doctests: bool,
+    /// `-v` or `-vv`: log what we're doing to stderr, `-vv` logs each item
+    /// visited. This hides rustc's `-v`.
+    #[serde(skip)]
+    pub verbose: u8,
+    /// `--output-dir DIR`: also write the stats for each file analysed to a
+    /// file of its own under `DIR`, with the same layout as the sources.
+    #[serde(skip)]
+    pub output_dir: Option<PathBuf>,
+    /// `--json-compact` or `--json-pretty`: write JSON on one line, or
+    /// indented. By default, JSON is indented only for a terminal, so files
+    /// under `--output-dir` are compact.
+    pub json_compact: Option<bool>,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Human readable text, the default.
+    Text,
+    /// A Graphviz graph of the call graph, needs `--call-graph`.
+    Dot,
+    /// Scalar stats as metrics in the Prometheus text format.
+    Metrics,
+    /// All the stats as JSON, which can be read back by `--merge`.
+    Json,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Colour output to a terminal, unless `NO_COLOR` is set. The default.
+    Auto,
+    Always,
+    Never,
+}
+
+impl Default for ColorMode {
+    fn default() -> ColorMode {
+        ColorMode::Auto
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorMode, String> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("unknown color `{}`, expected `auto`, `always`, or `never`", s)),
+        }
+    }
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format::Text
+    }
+}
+
+impl Format {
+    /// The extension for files in this format, e.g., with `--output-dir`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Dot => "dot",
+            Format::Metrics => "prom",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "dot" => Ok(Format::Dot),
+            "metrics" => Ok(Format::Metrics),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown format `{}`, expected `text`, `dot`, `metrics`, or `json`",
+                s
+            )),
+        }
+    }
+}
+
+impl Options {
+    /// Splits the command line into our own options and the arguments which
+    /// should be passed on to rustc untouched.
+    pub fn from_args(args: Vec<String>) -> Result<(Options, Vec<String>), String> {
+        let mut options = match config_path(&args)? {
+            Some(path) => Options::from_file(&path)?,
+            None => Options::default(),
+        };
+        let mut rustc_args = vec![];
+
+        // Options shared with rustc, as given on the command line.
+        let mut edition = None;
+        let mut cfgs = vec![];
+        let mut externs = vec![];
+        let mut lib_paths = vec![];
+        // And lists which replace, rather than add to, the config file's.
+        let mut exclude_globs = vec![];
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match &*arg {
+                "--config" => {
+                    // Already handled by `config_path`.
+                    args.next();
+                }
+                "--group-by-file" => options.group_by_file = true,
+                "--dump-hir" => options.dump_hir = Some(value(&arg, args.next())?),
+                "--check-naming" => options.check_naming = true,
+                "--exclude-glob" => exclude_globs.push(value(&arg, args.next())?),
+                "--max-file-size" => {
+                    let value = value(&arg, args.next())?;
+                    let size = value.parse().map_err(|_| {
+                        format!("`--max-file-size` expects a number of bytes, found `{}`", value)
+                    })?;
+                    options.max_file_size = Some(size);
+                }
+                "--repl" => options.repl = true,
+                "--merge" => options.merge = true,
+                "--version-info" => options.version_info = true,
+                "--fail-on-warnings" => options.fail_on_warnings = true,
+                "--format" => options.format = value(&arg, args.next())?.parse()?,
+                "--call-graph" => options.call_graph = true,
+                "--stmt-ratio" => options.stmt_ratio = true,
+                "--explain" => options.explain = true,
+                "--full-paths" => options.full_paths = true,
+                "--doctests" => options.doctests = true,
+                "-v" => options.verbose = options.verbose.max(1),
+                "-vv" => options.verbose = 2,
+                "--with-churn" => options.with_churn = true,
+                "--uninstantiated-generics" => options.uninstantiated_generics = true,
+                "--sample-rate" => {
+                    let value = value(&arg, args.next())?;
+                    let rate = value.parse().map_err(|_| {
+                        format!("`--sample-rate` expects a number, found `{}`", value)
+                    })?;
+                    options.sample_rate = Some(rate);
+                }
+                "--seed" => {
+                    let value = value(&arg, args.next())?;
+                    let seed = value
+                        .parse()
+                        .map_err(|_| format!("`--seed` expects a number, found `{}`", value))?;
+                    options.seed = Some(seed);
+                }
+                "--fail-fast" => options.fail_fast = true,
+                "--keep-going" => options.fail_fast = false,
+                "--magic-number-allow" => {
+                    let value = value(&arg, args.next())?;
+                    let allow = value
+                        .split(',')
+                        .map(|n| n.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| {
+                            format!("`--magic-number-allow` expects numbers, found `{}`", value)
+                        })?;
+                    options.magic_number_allow = Some(allow);
+                }
+                "--module-path" => options.module_path = Some(value(&arg, args.next())?),
+                "--json-compact" => options.json_compact = Some(true),
+                "--json-pretty" => options.json_compact = Some(false),
+                "--output-dir" => options.output_dir = Some(value(&arg, args.next())?.into()),
+                "--jobs" => {
+                    let value = value(&arg, args.next())?;
+                    let jobs = value
+                        .parse()
+                        .map_err(|_| format!("`--jobs` expects a number, found `{}`", value))?;
+                    options.jobs = Some(jobs);
+                }
+                "--edition" => {
+                    let value = value(&arg, args.next())?;
+                    edition = Some(value.clone());
+                    rustc_args.push(arg);
+                    rustc_args.push(value);
+                }
+                "--cfg" => {
+                    let value = value(&arg, args.next())?;
+                    cfgs.push(value.clone());
+                    rustc_args.push(arg);
+                    rustc_args.push(value);
+                }
+                "--extern" => {
+                    let value = value(&arg, args.next())?;
+                    externs.push(extern_spec(&value)?);
+                    rustc_args.push(arg);
+                    rustc_args.push(value);
+                }
+                "-L" => {
+                    let value = value(&arg, args.next())?;
+                    lib_paths.push(value.clone());
+                    rustc_args.push(arg);
+                    rustc_args.push(value);
+                }
+                "--color" => {
+                    let value = value(&arg, args.next())?;
+                    options.color = value.parse()?;
+                    rustc_args.push(arg);
+                    rustc_args.push(value);
+                }
+                _ if arg.starts_with("--color=") => {
+                    options.color = arg["--color=".len()..].parse()?;
+                    rustc_args.push(arg);
+                }
+                _ if arg.starts_with("--edition=") => {
+                    edition = Some(arg["--edition=".len()..].to_owned());
+                    rustc_args.push(arg);
+                }
+                _ if arg.starts_with("--extern=") => {
+                    externs.push(extern_spec(&arg["--extern=".len()..])?);
+                    rustc_args.push(arg);
+                }
+                _ if arg.starts_with("-L") => {
+                    lib_paths.push(arg["-L".len()..].to_owned());
+                    rustc_args.push(arg);
+                }
+                _ => rustc_args.push(arg),
+            }
+        }
+
+        // rustc only sees the command line, so pass on anything shared which
+        // came from the config file.
+        match edition {
+            Some(edition) => options.edition = Some(edition),
+            None => {
+                if let Some(edition) = &options.edition {
+                    rustc_args.push("--edition".to_owned());
+                    rustc_args.push(edition.clone());
+                }
+            }
+        }
+        if cfgs.is_empty() {
+            for cfg in &options.cfgs {
+                rustc_args.push("--cfg".to_owned());
+                rustc_args.push(cfg.clone());
+            }
+        } else {
+            options.cfgs = cfgs;
+        }
+        if externs.is_empty() {
+            for spec in &options.externs {
+                rustc_args.push("--extern".to_owned());
+                rustc_args.push(spec.clone());
+            }
+        } else {
+            options.externs = externs;
+        }
+        if lib_paths.is_empty() {
+            for path in &options.lib_paths {
+                rustc_args.push("-L".to_owned());
+                rustc_args.push(path.clone());
+            }
+        } else {
+            options.lib_paths = lib_paths;
+        }
+
+        if !exclude_globs.is_empty() {
+            options.exclude_globs = exclude_globs;
+        }
+
+        // Check the edition here, rather than leaving it to the driver, so we
+        // can say which editions this build of rustc knows about.
+        if let Some(edition) = &options.edition {
+            if edition.parse::<Edition>().is_err() {
+                let supported: Vec<_> = ALL_EDITIONS.iter().map(|e| format!("`{}`", e)).collect();
+                return Err(format!(
+                    "unsupported edition `{}`, this rustc supports {}",
+                    edition,
+                    supported.join(", ")
+                ));
+            }
+        }
+        if let Some(rate) = options.sample_rate {
+            if !(rate > 0.0 && rate <= 1.0) {
+                return Err(format!("`--sample-rate` must be in (0, 1], found `{}`", rate));
+            }
+        }
+        if options.jobs == Some(0) {
+            return Err("`--jobs` must be at least 1".to_owned());
+        }
+        if options.format == Format::Dot && !options.call_graph {
+            return Err("`--format dot` requires `--call-graph`".to_owned());
+        }
+
+        Ok((options, rustc_args))
+    }
+
+    /// Is the literal `n` allowed by `--magic-number-allow`?
+    pub fn allows_magic_number(&self, n: f64) -> bool {
+        match &self.magic_number_allow {
+            Some(allow) => allow.contains(&n),
+            None => n == 0.0 || n == 1.0,
+        }
+    }
+
+    fn from_file(path: &str) -> Result<Options, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read config file `{}`: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("invalid config file `{}`: {}", path, e))
+    }
+}
+
+// Finds the `--config FILE` option, if there is one.
+fn config_path(args: &[String]) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == "--config") {
+        Some(i) => value("--config", args.get(i + 1).cloned()).map(Some),
+        None => Ok(None),
+    }
+}
+
+// Checks the value of `--extern`, which is `NAME=PATH` or just `NAME`.
+fn extern_spec(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.starts_with('=') {
+        return Err(format!("`--extern` expects `NAME=PATH`, found `{}`", value));
+    }
+    Ok(value.to_owned())
+}
+
+// The value for an option which takes one.
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("`{}` requires a value", option))
+}
@@ -0,0 +1,1066 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
+
+use crate::options::{ColorMode, Format, Options};
+use crate::stats::{FnStats, Inline, Layout, Stats, Stub, TOP_N};
+
+/// Writes the stats in the format asked for by `--format`.
+pub fn write(stats: &Stats, options: &Options, out: &mut dyn WriteColor) -> io::Result<()> {
+    match options.format {
+        Format::Text => write_text(stats, options, out),
+        Format::Dot => write_dot(stats, out),
+        Format::Metrics => write_metrics(stats, out),
+        Format::Json => write_json(stats, options, out),
+    }
+}
+
+/// Writes the stats as human readable text.
+pub fn write_text(stats: &Stats, options: &Options, out: &mut dyn WriteColor) -> io::Result<()> {
+    if let Some((sampled, total)) = stats.sample {
+        writeln!(
+            out,
+            "Estimated from a sample of {} of {} files: totals are scaled up, but lists only \
+             cover the sampled files;",
+            sampled, total
+        )?;
+    }
+
+    for (name, ty) in &stats.signatures {
+        out.set_color(&name_color())?;
+        write!(out, "{}", name)?;
+        out.reset()?;
+        writeln!(out, ":\t{}", ty)?;
+    }
+
+    if stats.warnings > 0 {
+        out.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+    }
+    write!(out, "Compiler emitted {} warnings;", stats.warnings)?;
+    out.reset()?;
+    writeln!(out)?;
+    explain(
+        out,
+        options,
+        "warnings emitted by the compiler up to the end of analysis, including lints",
+    )?;
+
+    writeln!(out, "Items:")?;
+    explain(
+        out,
+        options,
+        "items of each kind in the HIR, i.e., after macro expansion, so items from macros (e.g., \
+         derived impls) are included",
+    )?;
+    write_counts(&stats.items, out)?;
+    for (file, items) in &stats.items_by_file {
+        writeln!(out, "Items in {}:", file)?;
+        write_counts(items, out)?;
+    }
+
+    writeln!(out, "Items by visibility:")?;
+    explain(
+        out,
+        options,
+        "the declared visibility of each item and each item in an inherent impl; impls, extern \
+         blocks, and items from macros have none so aren't counted",
+    )?;
+    write_counts(&stats.visibilities, out)?;
+
+    writeln!(
+        out,
+        "Found {} const generic parameters and {} const generic arguments;",
+        stats.const_generic_params, stats.const_generic_args
+    )?;
+    explain(
+        out,
+        options,
+        "const parameters declared, and const arguments written out (inferred ones aren't counted)",
+    )?;
+
+    writeln!(
+        out,
+        "Found {} named lifetime parameters, {} uses of named lifetimes, and {} uses of `'_`;",
+        stats.named_lifetime_params, stats.named_lifetime_uses, stats.anonymous_lifetimes
+    )?;
+    explain(
+        out,
+        options,
+        "lifetimes written by the user; elided lifetimes aren't counted, `'static` counts as a \
+         use, and `'_` in impl headers is found from the source text",
+    )?;
+
+    let maybe_sized = stats.estimate(stats.maybe_sized_params.values().sum());
+    writeln!(out, "Found {} `?Sized` type parameters;", maybe_sized)?;
+    explain(out, options, "type parameters with a `?Sized` bound, inline or in a where clause")?;
+    for (owner, count) in &stats.maybe_sized_params {
+        write_named(out, owner, format_args!(": {}", count))?;
+    }
+
+    writeln!(
+        out,
+        "Found {} associated type equality bounds (e.g., `T: Iterator<Item = u8>`);",
+        stats.assoc_type_equality_bounds
+    )?;
+    explain(
+        out,
+        options,
+        "`Name = Type` in bounds and where clauses, but not in types like \
+         `dyn Iterator<Item = u8>`",
+    )?;
+
+    let generic = stats.functions.iter().filter(|f| f.generic).count();
+    writeln!(
+        out,
+        "Generic functions: {} ({:.0}%), concrete: {};",
+        stats.estimate(generic),
+        percent(generic, stats.functions.len()),
+        stats.estimate(stats.functions.len() - generic)
+    )?;
+    explain(
+        out,
+        options,
+        "functions with type or const parameters of their own, including `impl Trait` arguments; \
+         those of an enclosing impl or trait don't count",
+    )?;
+
+    if options.uninstantiated_generics {
+        writeln!(
+            out,
+            "Found {} generic functions which are never instantiated;",
+            stats.estimate(stats.uninstantiated_generics.len())
+        )?;
+        explain(
+            out,
+            options,
+            "generic functions and methods (including in generic impls) with no monomorphized \
+             instance in this crate; uses by other crates aren't seen",
+        )?;
+        for name in &stats.uninstantiated_generics {
+            write_named(out, name, format_args!(""))?;
+        }
+    }
+
+    let mut_ref_params: usize = stats.functions.iter().map(|f| f.mut_ref_params).sum();
+    let mut_bindings: usize = stats.functions.iter().map(|f| f.mut_bindings).sum();
+    writeln!(
+        out,
+        "Found {} `&mut` parameters and {} `mut` bindings in {} functions;",
+        stats.estimate(mut_ref_params),
+        stats.estimate(mut_bindings),
+        stats.estimate(stats.functions.len())
+    )?;
+    explain(
+        out,
+        options,
+        "`&mut T` parameters (including `&mut self`), and `mut` bindings in any pattern",
+    )?;
+
+    if options.stmt_ratio {
+        let ratios: Vec<_> = stats.functions.iter().map(|f| f.stmt_ratio()).collect();
+        let average = if ratios.is_empty() {
+            0.0
+        } else {
+            ratios.iter().sum::<f64>() / ratios.len() as f64
+        };
+        writeln!(out, "Statements per expression: {:.2} on average;", average)?;
+        explain(
+            out,
+            options,
+            "statements divided by expressions in each body, closures included, averaged over \
+             functions",
+        )?;
+        for (f, ratio) in stats.functions.iter().zip(ratios) {
+            writeln!(
+                out,
+                "    {}: {:.2} ({} statements, {} expressions)",
+                f.name, ratio, f.stmts, f.exprs
+            )?;
+        }
+    }
+
+    let single_exit = stats.functions.iter().filter(|f| f.early_returns == 0).count();
+    writeln!(
+        out,
+        "Functions with a single exit: {} ({:.0}%), with early returns: {};",
+        stats.estimate(single_exit),
+        percent(single_exit, stats.functions.len()),
+        stats.estimate(stats.functions.len() - single_exit)
+    )?;
+    explain(
+        out,
+        options,
+        "a `return` is early unless it ends the body; those from `?` and in closures aren't \
+         counted",
+    )?;
+    for f in stats.functions.iter().filter(|f| f.early_returns > 0) {
+        write_named(out, &f.name, format_args!(": {} early returns", f.early_returns))?;
+    }
+
+    let slices = stats.index_ops.iter().filter(|op| op.slice).count();
+    let unchecked = stats.index_ops.iter().filter(|op| !op.len_checked).count();
+    writeln!(
+        out,
+        "Found {} indexing and {} slicing operations which could panic, {} with no length check;",
+        stats.estimate(stats.index_ops.len() - slices),
+        stats.estimate(slices),
+        stats.estimate(unchecked)
+    )?;
+    explain(
+        out,
+        options,
+        "`v[i]` and `v[i..j]` in function bodies, but not `v[..]`; an operation is checked if any \
+         `.len()` or `.is_empty()` comes before it in the function, which is only a guess",
+    )?;
+    for op in &stats.index_ops {
+        writeln!(
+            out,
+            "    {}: {} in {}{}",
+            op.location,
+            if op.slice { "slicing" } else { "indexing" },
+            op.function,
+            if op.len_checked { "" } else { " (unchecked)" }
+        )?;
+    }
+
+    writeln!(
+        out,
+        "Found {} `match`es, {} `if let`s, and {} `while let`s;",
+        stats.matches, stats.if_lets, stats.while_lets
+    )?;
+    explain(
+        out,
+        options,
+        "each `match` is classified by the syntax it was desugared from; those from macros (e.g., \
+         `println!`) aren't counted",
+    )?;
+
+    writeln!(
+        out,
+        "Found {} labeled loops, {} labeled blocks, {} `break`s or `continue`s to a label, \
+         and {} `break`s with a value;",
+        stats.labeled_loops, stats.labeled_blocks, stats.labeled_jumps, stats.break_values
+    )?;
+    explain(
+        out,
+        options,
+        "labels and jumps written by the user; those from desugaring (e.g., `for`) don't count",
+    )?;
+
+    writeln!(
+        out,
+        "Found {} blanket trait impls and {} concrete trait impls;",
+        stats.estimate(stats.blanket_impls.len()),
+        stats.concrete_trait_impls
+    )?;
+    explain(
+        out,
+        options,
+        "blanket impls are those whose self type is a type parameter, e.g., `impl<T> Trait for T`",
+    )?;
+    for trait_name in &stats.blanket_impls {
+        writeln!(out, "    impl<T> {} for T", trait_name)?;
+    }
+
+    if !stats.layouts.is_empty() {
+        // Biggest first, since those are the interesting ones.
+        let mut layouts: Vec<_> = stats.layouts.iter().collect();
+        layouts.sort_by_key(|(_, layout)| match layout {
+            Layout::Known { size, .. } => Reverse(*size),
+            _ => Reverse(0),
+        });
+
+        writeln!(out, "Type layouts:")?;
+        explain(
+            out,
+            options,
+            "size and alignment as laid out by the compiler for the target; generic types have no \
+             layout",
+        )?;
+        for (name, layout) in layouts {
+            match layout {
+                Layout::Known { size, align } => {
+                    write_named(out, name, format_args!(": {} bytes, aligned to {}", size, align))?
+                }
+                Layout::Generic => write_named(out, name, format_args!(": generic"))?,
+                Layout::Error(e) => write_named(out, name, format_args!(": unknown ({})", e))?,
+            }
+        }
+    }
+
+    let hand_written: usize = stats.impls.values().map(|c| c.hand_written).sum();
+    let derived: usize = stats.impls.values().map(|c| c.derived).sum();
+    writeln!(
+        out,
+        "Found {} hand-written impls and {} derived impls;",
+        stats.estimate(hand_written),
+        stats.estimate(derived)
+    )?;
+    explain(
+        out,
+        options,
+        "impls marked `#[automatically_derived]` (i.e., from `#[derive(..)]`) are derived",
+    )?;
+    for (self_ty, counts) in &stats.impls {
+        writeln!(
+            out,
+            "    {}: {} hand-written, {} derived",
+            self_ty, counts.hand_written, counts.derived
+        )?;
+    }
+
+    writeln!(
+        out,
+        "Found {} impls with bounds beyond those on their type;",
+        stats.estimate(stats.extra_bound_impls.len())
+    )?;
+    explain(
+        out,
+        options,
+        "bounds (including where clauses) on hand-written impls of structs, enums, and unions \
+         which the type's own bounds don't imply",
+    )?;
+    for (description, bounds) in &stats.extra_bound_impls {
+        write_named(out, description, format_args!(": {}", bounds.join(", ")))?;
+    }
+
+    writeln!(
+        out,
+        "Found {} impls for references, {} for smart pointers, and {} for other types;",
+        stats.estimate(stats.reference_impls.len()),
+        stats.estimate(stats.smart_pointer_impls.len()),
+        stats.value_impls
+    )?;
+    explain(
+        out,
+        options,
+        "self types which are `&T` or `&mut T`, or a `Box`, `Rc`, `Arc`, or `Pin`",
+    )?;
+    for description in &stats.reference_impls {
+        writeln!(out, "    reference: {}", description)?;
+    }
+    for description in &stats.smart_pointer_impls {
+        writeln!(out, "    smart pointer: {}", description)?;
+    }
+
+    writeln!(
+        out,
+        "Static dispatch calls: {}, dynamic dispatch calls: {};",
+        stats.static_dispatch_calls, stats.dynamic_dispatch_calls
+    )?;
+    explain(
+        out,
+        options,
+        "method calls only, a call is dynamic if it is to a trait method on a trait object",
+    )?;
+
+    writeln!(out, "Found {} `Drop` impls;", stats.estimate(stats.drop_impls.len()))?;
+    explain(out, options, "types with an `impl Drop`")?;
+    for self_ty in &stats.drop_impls {
+        write_named(out, self_ty, format_args!(""))?;
+    }
+
+    let referring: BTreeSet<_> = stats.const_refs.iter().map(|(from, _)| from).collect();
+    writeln!(
+        out,
+        "Found {} consts and statics which refer to other consts or statics;",
+        stats.estimate(referring.len())
+    )?;
+    explain(
+        out,
+        options,
+        "paths to consts, associated consts, and statics in initializers, each pair counted once",
+    )?;
+    if !stats.const_refs.is_empty() {
+        let mut referenced: BTreeMap<&str, usize> = BTreeMap::new();
+        for (_, to) in &stats.const_refs {
+            *referenced.entry(to).or_insert(0) += 1;
+        }
+        let mut referenced: Vec<_> = referenced.into_iter().collect();
+        referenced.sort_by_key(|&(_, count)| Reverse(count));
+        writeln!(out, "Most referenced consts and statics:")?;
+        for (name, count) in referenced.into_iter().take(TOP_N) {
+            write_named(out, name, format_args!(": referred to by {}", count))?;
+        }
+    }
+
+    writeln!(
+        out,
+        "Found {} types with trait objects in their fields;",
+        stats.estimate(stats.trait_object_fields.len())
+    )?;
+    explain(
+        out,
+        options,
+        "`dyn Trait` anywhere in a field's type; `dyn Send` and other auto traits alone don't \
+         count",
+    )?;
+    for (name, traits) in &stats.trait_object_fields {
+        write_named(out, name, format_args!(": {}", traits.join(", ")))?;
+    }
+
+    if !stats.receivers.is_empty() {
+        let mut total = BTreeMap::new();
+        for receivers in stats.receivers.values() {
+            for (receiver, count) in receivers {
+                *total.entry(receiver.clone()).or_insert(0) += count;
+            }
+        }
+        for count in total.values_mut() {
+            *count = stats.estimate(*count);
+        }
+        let count = |receivers: &BTreeMap<String, usize>, receiver: &str| {
+            receivers.get(receiver).cloned().unwrap_or(0)
+        };
+        let line = |receivers: &BTreeMap<String, usize>| {
+            format!(
+                "by value: {}, by ref: {}, by mut ref: {}, by box/pin: {}, no receiver: {}",
+                count(receivers, "by value"),
+                count(receivers, "by ref"),
+                count(receivers, "by mut ref"),
+                count(receivers, "by box/pin"),
+                count(receivers, "no receiver")
+            )
+        };
+        writeln!(out, "Methods by receiver: {};", line(&total))?;
+        explain(
+            out,
+            options,
+            "from the type of the first parameter; `Rc` and `Arc` receivers count as box/pin",
+        )?;
+        for (owner, receivers) in &stats.receivers {
+            write_named(out, owner, format_args!(": {}", line(receivers)))?;
+        }
+    }
+
+    if !stats.accessor_pairs.is_empty() {
+        writeln!(out, "`&self`/`&mut self` method pairs:")?;
+        explain(
+            out,
+            options,
+            "methods like `x`/`x_mut` or `x_ref`/`x_mut` on the same type taking \
+             `&self`/`&mut self`",
+        )?;
+        for (self_ty, by_ref, by_mut) in &stats.accessor_pairs {
+            write_named(out, self_ty, format_args!(": {} / {}", by_ref, by_mut))?;
+        }
+    }
+
+    if !stats.closure_captures.is_empty() {
+        writeln!(out, "Closures by number of captured variables:")?;
+        explain(out, options, "variables captured by each closure, whatever the capture mode")?;
+        for (captures, count) in &stats.closure_captures {
+            writeln!(out, "    {}: {}", captures, count)?;
+        }
+        writeln!(out, "Closures with the most captures:")?;
+        for (location, captures) in &stats.largest_closures {
+            writeln!(out, "    {} captures {}", location, captures)?;
+        }
+    }
+
+    let try_ops: usize = stats.functions.iter().map(|f| f.try_ops).sum();
+    writeln!(out, "Found {} uses of `?`;", stats.estimate(try_ops))?;
+    explain(out, options, "each `?` counts once, including those in closures")?;
+    for f in stats.functions.iter().filter(|f| f.try_ops > 0) {
+        write_named(out, &f.name, format_args!(": {}", f.try_ops))?;
+    }
+
+    let raw_ptr_fns: Vec<_> = stats
+        .functions
+        .iter()
+        .filter(|f| f.const_ptrs > 0 || f.mut_ptrs > 0)
+        .collect();
+    let raw_ptr_fn_count = stats.estimate(raw_ptr_fns.len());
+    writeln!(out, "Found {} functions taking or returning raw pointers;", raw_ptr_fn_count)?;
+    explain(out, options, "`*const T` and `*mut T` anywhere in the parameter or return types")?;
+    for f in raw_ptr_fns {
+        write_named(
+            out,
+            &f.name,
+            format_args!(": {} `*const`, {} `*mut`", f.const_ptrs, f.mut_ptrs),
+        )?;
+    }
+
+    if !stats.abi_fns.is_empty() {
+        writeln!(out, "Functions by ABI:")?;
+        explain(out, options, "functions with bodies and a non-Rust ABI, e.g., `extern \"C\" fn`")?;
+        write_counts(&stats.abi_fns, out)?;
+    }
+    if !stats.abi_fn_ptrs.is_empty() {
+        writeln!(out, "Function pointer types by ABI:")?;
+        explain(out, options, "function pointer types written with a non-Rust ABI")?;
+        write_counts(&stats.abi_fn_ptrs, out)?;
+    }
+    writeln!(out, "Found {} items in `extern` blocks;", stats.foreign_items)?;
+    explain(out, options, "functions and statics declared in `extern` blocks")?;
+
+    for (level, lints) in &stats.lint_levels {
+        writeln!(out, "Lints set to `{}`:", level)?;
+        explain(
+            out,
+            options,
+            "each lint in an attribute written by the user counts once; attributes from macros and \
+             derives aren't counted",
+        )?;
+        write_counts(lints, out)?;
+    }
+
+    let magic_numbers: usize = stats.magic_numbers.values().sum();
+    writeln!(out, "Found {} magic numbers;", magic_numbers)?;
+    explain(
+        out,
+        options,
+        "numeric literals in function bodies not allowed by `--magic-number-allow`, ignoring those \
+         from macros",
+    )?;
+    for f in stats.functions.iter().filter(|f| f.magic_numbers > 0) {
+        write_named(out, &f.name, format_args!(": {}", f.magic_numbers))?;
+    }
+    if !stats.magic_numbers.is_empty() {
+        let mut common: Vec<_> = stats.magic_numbers.iter().collect();
+        common.sort_by_key(|&(_, count)| Reverse(count));
+        writeln!(out, "Most common magic numbers:")?;
+        for (n, count) in common.into_iter().take(TOP_N) {
+            writeln!(out, "    {}: {}", n, count)?;
+        }
+    }
+
+    let inline_count = |inline| {
+        stats.estimate(stats.functions.iter().filter(|f| f.inline == inline).count())
+    };
+    writeln!(
+        out,
+        "Found {} functions without `#[inline]`, {} with `#[inline]`, {} with \
+         `#[inline(always)]`, and {} with `#[inline(never)]`;",
+        inline_count(Inline::None),
+        inline_count(Inline::Hint),
+        inline_count(Inline::Always),
+        inline_count(Inline::Never)
+    )?;
+    explain(out, options, "from the `#[inline]` attribute on each function with a body")?;
+    for f in &stats.functions {
+        match f.inline {
+            Inline::Always => write_named(out, &f.name, format_args!(": always"))?,
+            Inline::Never => write_named(out, &f.name, format_args!(": never"))?,
+            _ => {}
+        }
+    }
+
+    // Complex code which changes often is the most likely to need work.
+    let mut churned: Vec<_> = stats
+        .functions
+        .iter()
+        .filter_map(|f| f.churn.map(|churn| (f, churn, f.complexity * churn)))
+        .collect();
+    if !churned.is_empty() {
+        churned.sort_by_key(|&(_, _, priority)| Reverse(priority));
+        writeln!(out, "Functions by complexity × churn:")?;
+        explain(
+            out,
+            options,
+            "cyclomatic complexity is one plus each extra `match` arm (including desugared `if`s \
+             and loops) and each `&&` or `||`, counted pre-monomorphization with macro-expanded \
+             branches included; churn is the number of commits touching the function's lines, from \
+             `git log -L`",
+        )?;
+        for (f, churn, priority) in churned {
+            writeln!(
+                out,
+                "    {}: {} (complexity {}, {} commits)",
+                f.name, priority, f.complexity, churn
+            )?;
+        }
+    }
+
+    let stubs: Vec<_> = stats
+        .functions
+        .iter()
+        .filter_map(|f| f.stub.map(|stub| (&f.name, stub)))
+        .collect();
+    writeln!(out, "Found {} empty or stub functions;", stats.estimate(stubs.len()))?;
+    explain(out, options, "bodies which are empty or just `todo!()` or `unimplemented!()`")?;
+    for (name, stub) in stubs {
+        let stub = match stub {
+            Stub::Empty => "empty",
+            Stub::Todo => "`todo!()`",
+            Stub::Unimplemented => "`unimplemented!()`",
+        };
+        write_named(out, name, format_args!(": {}", stub))?;
+    }
+
+    if !stats.must_use.is_empty() {
+        writeln!(out, "`#[must_use]` items:")?;
+        explain(
+            out,
+            options,
+            "items with a `#[must_use]` attribute of their own, by kind; a `#[must_use]` type \
+             doesn't count for the functions which return it",
+        )?;
+        write_counts(&stats.must_use, out)?;
+    }
+    if !stats.deprecated.is_empty() {
+        writeln!(out, "`#[deprecated]` items:")?;
+        explain(
+            out,
+            options,
+            "items with a `#[deprecated]` attribute of their own, not those inside a deprecated \
+             module or impl",
+        )?;
+        write_counts(&stats.deprecated, out)?;
+        writeln!(out, "Deprecations:")?;
+        for deprecation in &stats.deprecations {
+            let since = match &deprecation.since {
+                Some(since) => format!(" since {}", since),
+                None => String::new(),
+            };
+            let note = match &deprecation.note {
+                Some(note) => format!(": {}", note),
+                None => String::new(),
+            };
+            write_named(
+                out,
+                &deprecation.name,
+                format_args!(" ({}){}{}", deprecation.kind, since, note),
+            )?;
+        }
+    }
+
+    if !stats.conversions.is_empty() {
+        let mut by_trait = BTreeMap::new();
+        for conversion in &stats.conversions {
+            *by_trait.entry(conversion.trait_name.clone()).or_insert(0) += 1;
+        }
+        writeln!(out, "Conversion impls:")?;
+        explain(
+            out,
+            options,
+            "impls of `From`, `Into`, `TryFrom`, and `TryInto`, including generic ones; the \
+             blanket `Into` impl for each `From` isn't counted",
+        )?;
+        write_counts(&by_trait, out)?;
+        writeln!(out, "Conversions:")?;
+        for conversion in &stats.conversions {
+            writeln!(
+                out,
+                "    {} -> {} ({})",
+                conversion.from, conversion.to, conversion.trait_name
+            )?;
+        }
+    }
+
+    if !stats.supertraits.is_empty() {
+        writeln!(out, "Supertraits:")?;
+        explain(out, options, "bounds on the trait itself, not those in where clauses")?;
+        for (name, supertraits) in &stats.supertraits {
+            writeln!(
+                out,
+                "    {} has {}: {}",
+                name,
+                supertraits.len(),
+                supertraits.join(", ")
+            )?;
+        }
+
+        // The same information as an edge list, one `trait -> supertrait` per
+        // line, which is easy to feed to graph tools.
+        writeln!(out, "Supertrait edges:")?;
+        for (name, supertraits) in &stats.supertraits {
+            for supertrait in supertraits {
+                write_named(out, name, format_args!(" -> {}", supertrait))?;
+            }
+        }
+    }
+
+    if !stats.longest_names.is_empty() {
+        writeln!(out, "Longest names:")?;
+        explain(out, options, "names of items and associated items, except those from macros")?;
+        for (name, location) in &stats.longest_names {
+            write_named(out, name, format_args!(" ({} chars) at {}", name.len(), location))?;
+        }
+    }
+
+    if !stats.naming_violations.is_empty() {
+        writeln!(out, "Naming convention violations:")?;
+        explain(
+            out,
+            options,
+            "names which don't follow the usual Rust convention for their kind; names from macros \
+             aren't checked",
+        )?;
+        for violation in &stats.naming_violations {
+            writeln!(
+                out,
+                "    {}: {} `{}` should be {}",
+                violation.location, violation.kind, violation.name, violation.expected
+            )?;
+        }
+    }
+
+    if let Some(doctest_stats) = &stats.doctest_stats {
+        writeln!(
+            out,
+            "Found {} doctests, {} of which couldn't be compiled;",
+            stats.doctests, stats.failed_doctests
+        )?;
+        explain(
+            out,
+            options,
+            "code blocks in doc comments, analysed as separate crates wrapped in `fn main` like \
+             rustdoc does; ones which use the documented crate can't be compiled",
+        )?;
+        writeln!(out, "Items in doctests:")?;
+        write_counts(&doctest_stats.items, out)?;
+        let stmts: usize = doctest_stats.functions.iter().map(|f| f.stmts).sum();
+        writeln!(
+            out,
+            "    {} functions (including `main`s) with {} statements",
+            doctest_stats.estimate(doctest_stats.functions.len()),
+            doctest_stats.estimate(stmts)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The version of the JSON written by `write_json`. Bump this whenever a
+/// change to `Stats` means older stats can't be read (or would be read
+/// wrongly).
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct JsonOut<'a> {
+    schema_version: u32,
+    stats: &'a Stats,
+}
+
+// `read_json` checks the schema version before deserializing, so only the
+// stats are read here.
+#[derive(Deserialize)]
+struct JsonIn {
+    stats: Stats,
+}
+
+/// Writes all the stats as JSON, along with the schema version. The JSON is
+/// indented if stdout is a terminal and on one line otherwise, unless
+/// `--json-compact` or `--json-pretty` say which.
+pub fn write_json(stats: &Stats, options: &Options, out: &mut dyn WriteColor) -> io::Result<()> {
+    let json = JsonOut { schema_version: SCHEMA_VERSION, stats };
+    let compact = options.json_compact.unwrap_or_else(|| !atty::is(atty::Stream::Stdout));
+    if compact {
+        serde_json::to_writer(&mut *out, &json)?;
+    } else {
+        serde_json::to_writer_pretty(&mut *out, &json)?;
+    }
+    writeln!(out)
+}
+
+/// Reads stats written by `write_json`, checking they have the same schema
+/// version as we write. `name` is used in error messages.
+pub fn read_json(name: &str, input: &mut dyn Read) -> Result<Stats, String> {
+    // Check the version before the stats themselves, so if the schema has
+    // changed we can say so rather than complain about some field.
+    let json: serde_json::Value =
+        serde_json::from_reader(input).map_err(|e| format!("invalid JSON in `{}`: {}", name, e))?;
+    match json.get("schema_version").and_then(|v| v.as_u64()) {
+        Some(version) if version == u64::from(SCHEMA_VERSION) => {}
+        Some(version) => {
+            return Err(format!(
+                "`{}` has schema version {}, but this version of stupid-stats reads {}",
+                name, version, SCHEMA_VERSION
+            ))
+        }
+        None => return Err(format!("`{}` has no schema version", name)),
+    }
+
+    let json: JsonIn = serde_json::from_value(json)
+        .map_err(|e| format!("invalid stats in `{}`: {}", name, e))?;
+    Ok(json.stats)
+}
+
+/// Writes the call graph as a Graphviz digraph.
+pub fn write_dot(stats: &Stats, out: &mut dyn WriteColor) -> io::Result<()> {
+    // Every function is a node, even if it makes no calls and isn't called.
+    let mut names: BTreeSet<&str> = stats.functions.iter().map(|f| &*f.name).collect();
+    for (caller, callee) in &stats.call_graph {
+        names.insert(caller);
+        names.insert(callee);
+    }
+    // Paths aren't valid identifiers in DOT, so we number the nodes and use
+    // the path as a label.
+    let ids: BTreeMap<&str, usize> = names.into_iter().zip(0..).collect();
+
+    writeln!(out, "digraph calls {{")?;
+    for (name, id) in &ids {
+        writeln!(out, "    n{} [label=\"{}\"];", id, escape_dot(name))?;
+    }
+    for (caller, callee) in &stats.call_graph {
+        writeln!(out, "    n{} -> n{};", ids[&**caller], ids[&**callee])?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Writes the scalar stats as metrics in the Prometheus text exposition
+/// format, e.g., `stupid_stats_functions_total 42`. Lists of individual items
+/// aren't included, only counts.
+pub fn write_metrics(stats: &Stats, out: &mut dyn WriteColor) -> io::Result<()> {
+    if let Some((sampled, total)) = stats.sample {
+        writeln!(
+            out,
+            "# Estimated from a sample of {} of {} files, all counts are scaled up.",
+            sampled, total
+        )?;
+    }
+
+    let functions = &stats.functions;
+    // Counts from the lists of items need scaling up if we only have a
+    // sample, the rest already are.
+    let estimate = |n: usize| stats.estimate(n);
+    let sum = |f: fn(&FnStats) -> usize| -> usize { estimate(functions.iter().map(f).sum()) };
+
+    metric(out, "warnings_total", &[], stats.warnings)?;
+
+    for (kind, count) in &stats.items {
+        metric(out, "items_total", &[("kind", kind)], *count)?;
+    }
+    for (file, items) in &stats.items_by_file {
+        for (kind, count) in items {
+            metric(out, "items_by_file_total", &[("kind", kind), ("file", file)], *count)?;
+        }
+    }
+    for (visibility, count) in &stats.visibilities {
+        metric(out, "items_by_visibility_total", &[("visibility", visibility)], *count)?;
+    }
+
+    metric(out, "functions_total", &[], estimate(functions.len()))?;
+    let generic = estimate(functions.iter().filter(|f| f.generic).count());
+    metric(out, "generic_functions_total", &[], generic)?;
+    metric(out, "mut_ref_params_total", &[], sum(|f| f.mut_ref_params))?;
+    metric(out, "mut_bindings_total", &[], sum(|f| f.mut_bindings))?;
+    metric(out, "try_ops_total", &[], sum(|f| f.try_ops))?;
+    metric(out, "stmts_total", &[], sum(|f| f.stmts))?;
+    metric(out, "magic_numbers_total", &[], sum(|f| f.magic_numbers))?;
+    let raw_ptr_fns = functions.iter().filter(|f| f.const_ptrs > 0 || f.mut_ptrs > 0).count();
+    let raw_ptr_fns = estimate(raw_ptr_fns);
+    metric(out, "raw_ptr_functions_total", &[], raw_ptr_fns)?;
+    metric(out, "raw_ptrs_total", &[("mutability", "const")], sum(|f| f.const_ptrs))?;
+    metric(out, "raw_ptrs_total", &[("mutability", "mut")], sum(|f| f.mut_ptrs))?;
+    metric(out, "exprs_total", &[], sum(|f| f.exprs))?;
+    metric(out, "complexity_total", &[], sum(|f| f.complexity))?;
+    metric(out, "early_returns_total", &[], sum(|f| f.early_returns))?;
+    let slices = stats.index_ops.iter().filter(|op| op.slice).count();
+    let indexes = stats.index_ops.len() - slices;
+    metric(out, "index_ops_total", &[("kind", "index")], estimate(indexes))?;
+    metric(out, "index_ops_total", &[("kind", "slice")], estimate(slices))?;
+    let unchecked = stats.index_ops.iter().filter(|op| !op.len_checked).count();
+    metric(out, "unchecked_index_ops_total", &[], estimate(unchecked))?;
+    let early_return_fns = estimate(functions.iter().filter(|f| f.early_returns > 0).count());
+    metric(out, "early_return_functions_total", &[], early_return_fns)?;
+    for &(inline, name) in &[
+        (Inline::None, "none"),
+        (Inline::Hint, "hint"),
+        (Inline::Always, "always"),
+        (Inline::Never, "never"),
+    ] {
+        let count = estimate(functions.iter().filter(|f| f.inline == inline).count());
+        metric(out, "functions_by_inline_total", &[("inline", name)], count)?;
+    }
+    let uninstantiated = estimate(stats.uninstantiated_generics.len());
+    metric(out, "uninstantiated_generic_functions_total", &[], uninstantiated)?;
+    let stubs = estimate(functions.iter().filter(|f| f.stub.is_some()).count());
+    metric(out, "stub_functions_total", &[], stubs)?;
+
+    metric(out, "const_generic_params_total", &[], stats.const_generic_params)?;
+    metric(out, "const_generic_args_total", &[], stats.const_generic_args)?;
+    metric(out, "named_lifetime_params_total", &[], stats.named_lifetime_params)?;
+    metric(out, "named_lifetime_uses_total", &[], stats.named_lifetime_uses)?;
+    metric(out, "anonymous_lifetimes_total", &[], stats.anonymous_lifetimes)?;
+    metric(out, "assoc_type_equality_bounds_total", &[], stats.assoc_type_equality_bounds)?;
+    let maybe_sized = estimate(stats.maybe_sized_params.values().sum());
+    metric(out, "maybe_sized_params_total", &[], maybe_sized)?;
+
+    metric(out, "matches_total", &[("kind", "match")], stats.matches)?;
+    metric(out, "matches_total", &[("kind", "if-let")], stats.if_lets)?;
+    metric(out, "matches_total", &[("kind", "while-let")], stats.while_lets)?;
+    metric(out, "labeled_total", &[("kind", "loop")], stats.labeled_loops)?;
+    metric(out, "labeled_total", &[("kind", "block")], stats.labeled_blocks)?;
+    metric(out, "labeled_jumps_total", &[], stats.labeled_jumps)?;
+    metric(out, "break_values_total", &[], stats.break_values)?;
+
+    let blanket_impls = estimate(stats.blanket_impls.len());
+    metric(out, "trait_impls_total", &[("kind", "blanket")], blanket_impls)?;
+    metric(out, "trait_impls_total", &[("kind", "concrete")], stats.concrete_trait_impls)?;
+    let hand_written = estimate(stats.impls.values().map(|c| c.hand_written).sum());
+    let derived = estimate(stats.impls.values().map(|c| c.derived).sum());
+    metric(out, "impls_total", &[("origin", "hand-written")], hand_written)?;
+    metric(out, "impls_total", &[("origin", "derived")], derived)?;
+    metric(out, "drop_impls_total", &[], estimate(stats.drop_impls.len()))?;
+    metric(out, "extra_bound_impls_total", &[], estimate(stats.extra_bound_impls.len()))?;
+    for (kind, count) in &stats.must_use {
+        metric(out, "must_use_items_total", &[("kind", kind)], *count)?;
+    }
+    for (kind, count) in &stats.deprecated {
+        metric(out, "deprecated_items_total", &[("kind", kind)], *count)?;
+    }
+    let mut conversions = BTreeMap::new();
+    for conversion in &stats.conversions {
+        *conversions.entry(&*conversion.trait_name).or_insert(0) += 1;
+    }
+    for (trait_name, count) in conversions {
+        metric(out, "conversion_impls_total", &[("trait", trait_name)], estimate(count))?;
+    }
+    let reference_impls = estimate(stats.reference_impls.len());
+    let smart_pointer_impls = estimate(stats.smart_pointer_impls.len());
+    metric(out, "impls_by_self_type_total", &[("self", "reference")], reference_impls)?;
+    metric(out, "impls_by_self_type_total", &[("self", "smart-pointer")], smart_pointer_impls)?;
+    metric(out, "impls_by_self_type_total", &[("self", "value")], stats.value_impls)?;
+
+    let mut receivers = BTreeMap::new();
+    for counts in stats.receivers.values() {
+        for (receiver, count) in counts {
+            *receivers.entry(&**receiver).or_insert(0) += count;
+        }
+    }
+    for (receiver, count) in receivers {
+        metric(out, "methods_by_receiver_total", &[("receiver", receiver)], estimate(count))?;
+    }
+    metric(out, "method_calls_total", &[("dispatch", "static")], stats.static_dispatch_calls)?;
+    metric(out, "method_calls_total", &[("dispatch", "dynamic")], stats.dynamic_dispatch_calls)?;
+
+    for (captures, count) in &stats.closure_captures {
+        metric(out, "closures_total", &[("captures", &captures.to_string())], *count)?;
+    }
+
+    let supertrait_edges = estimate(stats.supertraits.iter().map(|s| s.1.len()).sum());
+    metric(out, "supertrait_edges_total", &[], supertrait_edges)?;
+    metric(out, "accessor_pairs_total", &[], estimate(stats.accessor_pairs.len()))?;
+    let naming_violations = estimate(stats.naming_violations.len());
+    metric(out, "naming_violations_total", &[], naming_violations)?;
+    metric(out, "const_refs_total", &[], estimate(stats.const_refs.len()))?;
+    for (abi, count) in &stats.abi_fns {
+        metric(out, "abi_functions_total", &[("abi", abi)], *count)?;
+    }
+    for (abi, count) in &stats.abi_fn_ptrs {
+        metric(out, "abi_fn_ptrs_total", &[("abi", abi)], *count)?;
+    }
+    metric(out, "foreign_items_total", &[], stats.foreign_items)?;
+    let trait_object_fields = estimate(stats.trait_object_fields.len());
+    metric(out, "trait_object_field_types_total", &[], trait_object_fields)?;
+    for (level, lints) in &stats.lint_levels {
+        for (lint, count) in lints {
+            metric(out, "lint_attributes_total", &[("level", level), ("lint", lint)], *count)?;
+        }
+    }
+    let compiled = stats.doctests - stats.failed_doctests;
+    metric(out, "doctests_total", &[("result", "compiled")], compiled)?;
+    metric(out, "doctests_total", &[("result", "failed")], stats.failed_doctests)?;
+    if let Some(doctest_stats) = &stats.doctest_stats {
+        for (kind, count) in &doctest_stats.items {
+            metric(out, "doctest_items_total", &[("kind", kind)], *count)?;
+        }
+    }
+    metric(out, "call_graph_edges_total", &[], estimate(stats.call_graph.len()))
+}
+
+// Writes one sample, e.g., `stupid_stats_items_total{kind="struct"} 3`.
+fn metric(
+    out: &mut dyn WriteColor,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: usize,
+) -> io::Result<()> {
+    write!(out, "stupid_stats_{}", name)?;
+    if !labels.is_empty() {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+            .collect();
+        write!(out, "{{{}}}", labels.join(","))?;
+    }
+    writeln!(out, " {}", value)
+}
+
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        100.0 * count as f64 / total as f64
+    }
+}
+
+fn write_counts(counts: &BTreeMap<String, usize>, out: &mut dyn WriteColor) -> io::Result<()> {
+    for (kind, count) in counts {
+        write!(out, "    ")?;
+        out.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+        write!(out, "{}", kind)?;
+        out.reset()?;
+        writeln!(out, ": {}", count)?;
+    }
+
+    Ok(())
+}
+
+// With `--explain`, says what the stats just written count, and how.
+fn explain(out: &mut dyn WriteColor, options: &Options, text: &str) -> io::Result<()> {
+    if !options.explain {
+        return Ok(());
+    }
+    out.set_color(ColorSpec::new().set_dimmed(true))?;
+    write!(out, "  = {}", text)?;
+    out.reset()?;
+    writeln!(out)
+}
+
+// Writes an indented line which starts with the name of an item, e.g.,
+// `    foo::bar: 3`, where `rest` is `: 3`.
+fn write_named(out: &mut dyn WriteColor, name: &str, rest: fmt::Arguments<'_>) -> io::Result<()> {
+    write!(out, "    ")?;
+    out.set_color(&name_color())?;
+    write!(out, "{}", name)?;
+    out.reset()?;
+    writeln!(out, "{}", rest)
+}
+
+fn name_color() -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_fg(Some(Color::Green));
+    spec
+}
+
+/// Whether to colour the output, from `--color` and `NO_COLOR`. By default we
+/// only colour output to a terminal, so piped output can be parsed.
+pub fn color_choice(options: &Options) -> ColorChoice {
+    match options.color {
+        ColorMode::Always => ColorChoice::Always,
+        ColorMode::Never => ColorChoice::Never,
+        ColorMode::Auto => {
+            if atty::is(atty::Stream::Stdout) && env::var_os("NO_COLOR").is_none() {
+                ColorChoice::Auto
+            } else {
+                ColorChoice::Never
+            }
+        }
+    }
+}
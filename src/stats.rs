@@ -0,0 +1,496 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The stats we collect about a crate. This is plain data, it doesn't borrow
+// anything from the compiler, so it can outlive the compilation session that
+// produced it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+// How many entries to keep in 'top n' lists, like the longest names.
+pub(crate) const TOP_N: usize = 10;
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Stats {
+    // Number of warnings emitted by the compiler (up to the end of analysis).
+    pub warnings: usize,
+    // The name and type of each function and method, as `(name, type)`.
+    pub signatures: Vec<(String, String)>,
+    // Number of items of each kind, e.g., "function" or "struct".
+    pub items: BTreeMap<String, usize>,
+    // The same counts, broken down by the source file the item is in. Only
+    // filled in with `--group-by-file`.
+    pub items_by_file: BTreeMap<String, BTreeMap<String, usize>>,
+    // Number of const generic parameters declared, e.g., `N` in
+    // `struct Foo<const N: usize>`.
+    pub const_generic_params: usize,
+    // Number of const arguments supplied to generic types or functions, e.g.,
+    // `N` in `Foo<N>` or `3` in `foo::<3>()`.
+    pub const_generic_args: usize,
+    // Each trait along with the names of its supertraits, i.e., the bounds in
+    // `trait Foo: Bar + Baz`.
+    pub supertraits: Vec<(String, Vec<String>)>,
+    // Every function and method with a body, except those from macros (e.g.,
+    // derives). Closures are counted as part of the function they are in.
+    pub functions: Vec<FnStats>,
+    // The longest names of items and associated items, longest first, with
+    // their locations.
+    pub longest_names: Vec<(String, String)>,
+    // Names which don't follow the usual Rust naming conventions. Only filled
+    // in with `--check-naming`.
+    pub naming_violations: Vec<NamingViolation>,
+    // Number of closures by how many variables they capture.
+    pub closure_captures: BTreeMap<usize, usize>,
+    // The closures which capture the most variables, as location and number of
+    // captures, most captures first.
+    pub largest_closures: Vec<(String, usize)>,
+    // Number of `match` expressions written as such.
+    pub matches: usize,
+    // Number of `if let` expressions.
+    pub if_lets: usize,
+    // Number of `while let` loops.
+    pub while_lets: usize,
+    // The traits implemented by blanket impls, i.e., `impl<T> Trait for T`,
+    // one entry per impl.
+    pub blanket_impls: Vec<String>,
+    // Number of impls of a trait for any other type.
+    pub concrete_trait_impls: usize,
+    // The layout of each struct, enum, and union.
+    pub layouts: Vec<(String, Layout)>,
+    // Pairs of methods on the same type which take `&self` and `&mut self`,
+    // e.g., `iter` and `iter_mut`, as type, `&self` method, `&mut self` method.
+    pub accessor_pairs: Vec<(String, String, String)>,
+    // Number of impls (inherent or of a trait) for each type.
+    pub impls: BTreeMap<String, ImplCounts>,
+    // Number of items with each visibility: "pub", "pub(crate)", "pub(super)",
+    // "pub(in path)", or "private". Impls and extern blocks don't count.
+    pub visibilities: BTreeMap<String, usize>,
+    // Number of named lifetime parameters declared, e.g., `'a` in `fn f<'a>`.
+    pub named_lifetime_params: usize,
+    // Number of uses of named lifetimes, including `'static`.
+    pub named_lifetime_uses: usize,
+    // Number of uses of the anonymous lifetime `'_`. Lifetimes which are
+    // elided altogether aren't counted.
+    pub anonymous_lifetimes: usize,
+    // Calls between functions in the crate, as `(caller, callee)`. Only
+    // filled in with `--call-graph`.
+    pub call_graph: BTreeSet<(String, String)>,
+    // Types with an `impl Drop`.
+    pub drop_impls: Vec<String>,
+    // Number of method calls dispatched statically.
+    pub static_dispatch_calls: usize,
+    // Number of calls of trait methods on trait objects.
+    pub dynamic_dispatch_calls: usize,
+    // Impls whose self type is a reference, e.g., `impl Display for &Foo`.
+    pub reference_impls: Vec<String>,
+    // Impls whose self type is a `Box`, `Rc`, `Arc`, or `Pin`.
+    pub smart_pointer_impls: Vec<String>,
+    // Number of impls for any other type.
+    pub value_impls: usize,
+    // Number of bounds which fix an associated type, e.g., `Item = u8` in
+    // `T: Iterator<Item = u8>`.
+    pub assoc_type_equality_bounds: usize,
+    // Number of uses of each magic number, i.e., a numeric literal in a
+    // function body which isn't allowed by `--magic-number-allow`.
+    pub magic_numbers: BTreeMap<String, usize>,
+    // References from the initializers of consts and statics to other consts
+    // and statics, as `(referring item, referenced item)`.
+    pub const_refs: BTreeSet<(String, String)>,
+    // Number of functions (with bodies) with each non-Rust ABI, e.g., "C" for
+    // `extern "C" fn`.
+    pub abi_fns: BTreeMap<String, usize>,
+    // Likewise for function pointer types, e.g., `extern "C" fn(i32)`.
+    pub abi_fn_ptrs: BTreeMap<String, usize>,
+    // Number of functions and statics declared in `extern` blocks.
+    pub foreign_items: usize,
+    // For each lint level attribute ("allow", "expect", "warn", "deny", or
+    // "forbid"), the number of times it is applied to each lint, e.g.,
+    // `#[allow(dead_code)]` counts once for "dead_code" under "allow".
+    pub lint_levels: BTreeMap<String, BTreeMap<String, usize>>,
+    // Each struct, enum, or union with trait objects in its fields, with the
+    // traits involved, e.g., `Box<dyn Fn()>` gives `std::ops::Fn`.
+    pub trait_object_fields: Vec<(String, Vec<String>)>,
+    // With `--sample-rate`, the number of files analysed and the number we
+    // could have analysed, as `(sampled, total)`. The scalar counts have been
+    // scaled up to estimate the totals, see `extrapolate`.
+    pub sample: Option<(usize, usize)>,
+    // For each type (for methods in impls) or trait (for methods declared in
+    // it), the number of methods by how they take `self`: "by value",
+    // "by ref", "by mut ref", "by box/pin" (also `Rc` and `Arc`), or
+    // "no receiver" for associated functions.
+    pub receivers: BTreeMap<String, BTreeMap<String, usize>>,
+    // Generic functions and methods which are never instantiated in the
+    // crate. Only filled in with `--uninstantiated-generics`.
+    pub uninstantiated_generics: Vec<String>,
+    // Number of loops (of any kind) and blocks with a label, e.g., `'outer:`.
+    pub labeled_loops: usize,
+    pub labeled_blocks: usize,
+    // Number of `break`s and `continue`s to a label, e.g., `break 'outer`.
+    pub labeled_jumps: usize,
+    // Number of `break`s with a value, e.g., `break x` in a `loop`.
+    pub break_values: usize,
+    // Hand-written impls with bounds beyond those on the type itself, with
+    // the extra bounds, e.g., `Foo<T>` and `T: Clone` for
+    // `impl<T: Clone> Foo<T>`.
+    pub extra_bound_impls: Vec<(String, Vec<String>)>,
+    // Number of items and associated items of each kind marked
+    // `#[must_use]`.
+    pub must_use: BTreeMap<String, usize>,
+    // Likewise for `#[deprecated]`, along with the details of each.
+    pub deprecated: BTreeMap<String, usize>,
+    pub deprecations: Vec<Deprecation>,
+    // Number of type parameters declared `?Sized`, for each function, type,
+    // trait, or impl which declares any.
+    pub maybe_sized_params: BTreeMap<String, usize>,
+    // Indexing and slicing in function bodies which could panic.
+    pub index_ops: Vec<IndexOp>,
+    // Impls of `From`, `Into`, `TryFrom`, and `TryInto`.
+    pub conversions: Vec<Conversion>,
+    // Number of doctests (code blocks in doc comments) found with
+    // `--doctests`, and how many of those couldn't be compiled.
+    pub doctests: usize,
+    pub failed_doctests: usize,
+    // The stats for all the doctests which could be compiled, together.
+    pub doctest_stats: Option<Box<Stats>>,
+}
+
+impl Stats {
+    /// Adds `other` to these stats, e.g., to combine the stats for several
+    /// crates.
+    pub fn merge(&mut self, other: Stats) {
+        self.warnings += other.warnings;
+        self.signatures.extend(other.signatures);
+        merge_counts(&mut self.items, other.items);
+        for (file, items) in other.items_by_file {
+            merge_counts(self.items_by_file.entry(file).or_default(), items);
+        }
+        self.const_generic_params += other.const_generic_params;
+        self.const_generic_args += other.const_generic_args;
+        self.supertraits.extend(other.supertraits);
+        self.functions.extend(other.functions);
+        self.longest_names.extend(other.longest_names);
+        self.naming_violations.extend(other.naming_violations);
+        merge_counts(&mut self.closure_captures, other.closure_captures);
+        self.largest_closures.extend(other.largest_closures);
+        self.matches += other.matches;
+        self.if_lets += other.if_lets;
+        self.while_lets += other.while_lets;
+        self.blanket_impls.extend(other.blanket_impls);
+        self.concrete_trait_impls += other.concrete_trait_impls;
+        self.layouts.extend(other.layouts);
+        self.accessor_pairs.extend(other.accessor_pairs);
+        merge_counts(&mut self.visibilities, other.visibilities);
+        self.named_lifetime_params += other.named_lifetime_params;
+        self.named_lifetime_uses += other.named_lifetime_uses;
+        self.anonymous_lifetimes += other.anonymous_lifetimes;
+        self.call_graph.extend(other.call_graph);
+        self.drop_impls.extend(other.drop_impls);
+        self.static_dispatch_calls += other.static_dispatch_calls;
+        self.dynamic_dispatch_calls += other.dynamic_dispatch_calls;
+        self.reference_impls.extend(other.reference_impls);
+        self.smart_pointer_impls.extend(other.smart_pointer_impls);
+        self.value_impls += other.value_impls;
+        self.assoc_type_equality_bounds += other.assoc_type_equality_bounds;
+        merge_counts(&mut self.magic_numbers, other.magic_numbers);
+        self.const_refs.extend(other.const_refs);
+        merge_counts(&mut self.abi_fns, other.abi_fns);
+        merge_counts(&mut self.abi_fn_ptrs, other.abi_fn_ptrs);
+        self.foreign_items += other.foreign_items;
+        self.trait_object_fields.extend(other.trait_object_fields);
+        self.sample = match (self.sample, other.sample) {
+            (Some((a, b)), Some((c, d))) => Some((a + c, b + d)),
+            (sample, None) | (None, sample) => sample,
+        };
+        self.uninstantiated_generics.extend(other.uninstantiated_generics);
+        self.labeled_loops += other.labeled_loops;
+        self.labeled_blocks += other.labeled_blocks;
+        self.labeled_jumps += other.labeled_jumps;
+        self.break_values += other.break_values;
+        self.extra_bound_impls.extend(other.extra_bound_impls);
+        merge_counts(&mut self.must_use, other.must_use);
+        merge_counts(&mut self.deprecated, other.deprecated);
+        self.deprecations.extend(other.deprecations);
+        merge_counts(&mut self.maybe_sized_params, other.maybe_sized_params);
+        self.conversions.extend(other.conversions);
+        self.index_ops.extend(other.index_ops);
+        self.doctests += other.doctests;
+        self.failed_doctests += other.failed_doctests;
+        if let Some(doctest_stats) = other.doctest_stats {
+            self.doctest_stats.get_or_insert_with(Default::default).merge(*doctest_stats);
+        }
+        for (owner, receivers) in other.receivers {
+            merge_counts(self.receivers.entry(owner).or_default(), receivers);
+        }
+        for (level, lints) in other.lint_levels {
+            merge_counts(self.lint_levels.entry(level).or_default(), lints);
+        }
+        for (self_ty, counts) in other.impls {
+            let entry = self.impls.entry(self_ty).or_default();
+            entry.hand_written += counts.hand_written;
+            entry.derived += counts.derived;
+        }
+
+        self.sort();
+    }
+
+    /// Scales the scalar counts up from stats for `sampled` files to estimates
+    /// for `total` files. Lists of individual items (functions, impls, and so
+    /// on) are left alone, they only cover the sampled files.
+    pub fn extrapolate(&mut self, sampled: usize, total: usize) {
+        self.sample = Some((sampled, total));
+        if sampled == 0 {
+            return;
+        }
+        let factor = total as f64 / sampled as f64;
+        let scale = |n: &mut usize| *n = (*n as f64 * factor).round() as usize;
+
+        for n in &mut [
+            &mut self.warnings,
+            &mut self.const_generic_params,
+            &mut self.const_generic_args,
+            &mut self.matches,
+            &mut self.if_lets,
+            &mut self.while_lets,
+            &mut self.concrete_trait_impls,
+            &mut self.named_lifetime_params,
+            &mut self.named_lifetime_uses,
+            &mut self.anonymous_lifetimes,
+            &mut self.static_dispatch_calls,
+            &mut self.dynamic_dispatch_calls,
+            &mut self.value_impls,
+            &mut self.assoc_type_equality_bounds,
+            &mut self.foreign_items,
+            &mut self.labeled_loops,
+            &mut self.labeled_blocks,
+            &mut self.labeled_jumps,
+            &mut self.break_values,
+            &mut self.doctests,
+            &mut self.failed_doctests,
+        ] {
+            scale(n);
+        }
+        for counts in &mut [
+            &mut self.items,
+            &mut self.visibilities,
+            &mut self.magic_numbers,
+            &mut self.abi_fns,
+            &mut self.abi_fn_ptrs,
+            &mut self.must_use,
+            &mut self.deprecated,
+        ] {
+            counts.values_mut().for_each(scale);
+        }
+        self.closure_captures.values_mut().for_each(scale);
+        if let Some(doctest_stats) = &mut self.doctest_stats {
+            doctest_stats.extrapolate(sampled, total);
+        }
+        for lints in self.lint_levels.values_mut() {
+            lints.values_mut().for_each(scale);
+        }
+    }
+
+    /// With `--sample-rate`, scales a count worked out from the lists of
+    /// items, which only cover the sampled files, up to an estimate for all
+    /// the files, as `extrapolate` does for the other counts.
+    pub fn estimate(&self, n: usize) -> usize {
+        match self.sample {
+            Some((sampled, total)) if sampled > 0 => {
+                (n as f64 * total as f64 / sampled as f64).round() as usize
+            }
+            _ => n,
+        }
+    }
+
+    /// Sorts the lists of items by name (or location), so the output doesn't
+    /// depend on the order things were found in, e.g., the order the files in
+    /// a batch finished in. This also trims the 'top n' lists.
+    pub fn sort(&mut self) {
+        self.signatures.sort();
+        self.supertraits.sort();
+        self.functions
+            .sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.location.cmp(&b.location)));
+        self.naming_violations
+            .sort_by(|a, b| a.location.cmp(&b.location).then_with(|| a.name.cmp(&b.name)));
+        self.blanket_impls.sort();
+        self.layouts.sort_by(|a, b| a.0.cmp(&b.0));
+        self.accessor_pairs.sort();
+        self.drop_impls.sort();
+        self.reference_impls.sort();
+        self.smart_pointer_impls.sort();
+        self.trait_object_fields.sort();
+        self.uninstantiated_generics.sort();
+        self.extra_bound_impls.sort();
+        self.deprecations
+            .sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.location.cmp(&b.location)));
+        self.index_ops.sort_by(|a, b| a.location.cmp(&b.location));
+        self.conversions.sort_by(|a, b| {
+            (&a.from, &a.to, &a.trait_name).cmp(&(&b.from, &b.to, &b.trait_name))
+        });
+
+        self.trim_top_lists();
+    }
+
+    // Sorts the 'top n' lists and throws away everything after the first n.
+    fn trim_top_lists(&mut self) {
+        self.longest_names.sort_by(|a, b| {
+            b.0.len()
+                .cmp(&a.0.len())
+                .then_with(|| a.0.cmp(&b.0))
+                .then_with(|| a.1.cmp(&b.1))
+        });
+        self.longest_names.truncate(TOP_N);
+        self.largest_closures
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        self.largest_closures.truncate(TOP_N);
+    }
+}
+
+fn merge_counts<K: Ord>(counts: &mut BTreeMap<K, usize>, other: BTreeMap<K, usize>) {
+    for (key, count) in other {
+        *counts.entry(key).or_insert(0) += count;
+    }
+}
+
+// Stats about a single function or method.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FnStats {
+    // Path to the function, e.g., `foo::Bar::baz`.
+    pub name: String,
+    // Where the function is defined, to tell apart functions with the same
+    // path (e.g., in impls of different traits).
+    pub location: String,
+    // Number of parameters with a `&mut T` type, including `&mut self`.
+    pub mut_ref_params: usize,
+    // Number of `mut x` bindings, in parameters, `let`s, or other patterns.
+    pub mut_bindings: usize,
+    // Number of uses of the `?` operator.
+    pub try_ops: usize,
+    pub inline: Inline,
+    // Does the function declare any type or const parameters (including
+    // `impl Trait` arguments)? Parameters of the enclosing impl don't count.
+    pub generic: bool,
+    // Is the body empty or just `todo!()` or `unimplemented!()`?
+    pub stub: Option<Stub>,
+    // Number of statements and expressions in the body, including those in
+    // closures.
+    pub stmts: usize,
+    pub exprs: usize,
+    // Number of magic numbers in the body.
+    pub magic_numbers: usize,
+    // Number of `*const T` and `*mut T` types in the parameters and return
+    // type.
+    pub const_ptrs: usize,
+    pub mut_ptrs: usize,
+    // Cyclomatic complexity, i.e., one more than the number of branches.
+    pub complexity: usize,
+    // Number of commits which changed the function. Only filled in with
+    // `--with-churn`, and only if git knows about the file.
+    pub churn: Option<usize>,
+    // Number of `return`s other than one at the very end of the body.
+    // Closures' returns and those from `?` don't count.
+    pub early_returns: usize,
+    // Number of indexing and slicing operations which could panic.
+    pub index_ops: usize,
+}
+
+impl FnStats {
+    /// Statements per expression, a rough measure of how imperative the
+    /// function is.
+    pub fn stmt_ratio(&self) -> f64 {
+        if self.exprs == 0 {
+            0.0
+        } else {
+            self.stmts as f64 / self.exprs as f64
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq, Serialize)]
+pub enum Stub {
+    Empty,
+    Todo,
+    Unimplemented,
+}
+
+// The `#[inline]` attribute on a function, if any.
+#[derive(Clone, Copy, Deserialize, PartialEq, Serialize)]
+pub enum Inline {
+    None,
+    Hint,
+    Always,
+    Never,
+}
+
+impl Default for Inline {
+    fn default() -> Inline {
+        Inline::None
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct ImplCounts {
+    pub hand_written: usize,
+    // Impls marked `#[automatically_derived]`, i.e., from `#[derive(..)]`.
+    pub derived: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NamingViolation {
+    pub name: String,
+    // The kind of item, e.g., "function" or "associated const".
+    pub kind: String,
+    // The convention the name should follow, e.g., "snake_case".
+    pub expected: String,
+    pub location: String,
+}
+
+// Indexing (`v[i]`) or slicing (`v[i..]`), either of which could panic.
+#[derive(Deserialize, Serialize)]
+pub struct IndexOp {
+    pub location: String,
+    // The function it is in.
+    pub function: String,
+    pub slice: bool,
+    // Is there a `.len()` or `.is_empty()` call before it in the function?
+    pub len_checked: bool,
+}
+
+// An impl of one of the conversion traits, e.g., `From<u8> for Foo`.
+#[derive(Deserialize, Serialize)]
+pub struct Conversion {
+    // `From`, `Into`, `TryFrom`, or `TryInto`.
+    pub trait_name: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Deprecation {
+    pub name: String,
+    pub location: String,
+    // The kind of item, e.g., "function".
+    pub kind: String,
+    // From `#[deprecated(since = "..", note = "..")]`.
+    pub since: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub enum Layout {
+    // Size and alignment in bytes.
+    Known { size: u64, align: u64 },
+    // A generic type has no layout until it is instantiated.
+    Generic,
+    // The compiler couldn't compute the layout, e.g., because the type is too
+    // big.
+    Error(String),
+}
@@ -0,0 +1,41 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Conversion impls, among impls of other traits.
+
+use stupid_stats::options::Options;
+
+// The derives are impls of `core` traits without a type parameter, which
+// mustn't be mistaken for conversions.
+const SOURCE: &str = "
+#[derive(Clone, Debug)]
+pub struct Celsius(pub f64);
+
+impl From<f64> for Celsius {
+    fn from(degrees: f64) -> Celsius {
+        Celsius(degrees)
+    }
+}
+
+impl Default for Celsius {
+    fn default() -> Celsius {
+        Celsius(0.0)
+    }
+}
+";
+
+#[test]
+fn only_conversions() {
+    let stats =
+        stupid_stats::analyze_str("lib.rs", SOURCE.to_owned(), &Options::default()).unwrap();
+    assert_eq!(stats.conversions.len(), 1);
+    let conversion = &stats.conversions[0];
+    assert_eq!(conversion.trait_name, "From");
+    assert_eq!(conversion.from, "f64");
+    assert_eq!(conversion.to, "Celsius");
+}
@@ -0,0 +1,77 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The output shouldn't depend on the order the files in a batch finish in.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+// Several files with functions and deprecations of the same name, so their
+// order in the output depends on more than the name.
+const FILES: &[(&str, &str)] = &[
+    ("a.rs", "pub fn new() -> u8 { 1 }\n#[deprecated]\npub fn old() {}\n"),
+    ("b.rs", "pub fn new() -> u16 { 2 }\n#[deprecated(note = \"b\")]\npub fn old() {}\n"),
+    ("c.rs", "pub struct Foo;\nimpl Foo {\n    pub fn new() -> Foo { Foo }\n}\n"),
+    ("d.rs", "pub fn new() -> u32 { 3 }\npub fn other(x: &mut u32) { *x += 1; }\n"),
+    ("e.rs", IMPLS),
+    ("f.rs", IMPLS),
+];
+
+// Structs, impls, and conversions, in more than one file so the layouts and
+// the lists of impls and conversions have entries of the same name.
+const IMPLS: &str = "
+pub struct Foo<T>(pub T);
+pub struct Bar(pub u8, pub u64);
+
+impl<T: Clone> Foo<T> {
+    pub fn get(&self) -> T { self.0.clone() }
+}
+impl From<u8> for Bar {
+    fn from(n: u8) -> Bar { Bar(n, 0) }
+}
+impl From<Bar> for u8 {
+    fn from(bar: Bar) -> u8 { bar.0 }
+}
+impl Drop for Bar {
+    fn drop(&mut self) {}
+}
+impl<'a> PartialEq<u8> for &'a Bar {
+    fn eq(&self, n: &u8) -> bool { self.0 == *n }
+}
+pub trait Named {}
+impl<T> Named for T {}
+";
+
+fn run(dir: &Path, format: &str) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_stupid"))
+        .args(&["--jobs", "4", "--format", format])
+        .arg(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+#[test]
+fn same_output_twice() {
+    // Unique to this run, in case another is going at the same time.
+    let dir = env::temp_dir().join(format!("stupid-stats-deterministic-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (name, source) in FILES {
+        fs::write(dir.join(name), source).unwrap();
+    }
+
+    for format in &["json", "text"] {
+        let first = run(&dir, format);
+        assert!(!first.is_empty());
+        assert_eq!(first, run(&dir, format), "`--format {}` output changed", format);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,84 @@
+// Copyright 2015 Nicholas Cameron.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Analysing code which uses a compiled dependency, given with `--extern`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use stupid_stats::options::Options;
+
+const DEPENDENCY: &str = "pub struct Widget(pub u32);\n";
+
+const SOURCE: &str = "
+extern crate widgets;
+
+pub fn make() -> widgets::Widget {
+    widgets::Widget(3)
+}
+";
+
+// Compiles `DEPENDENCY` to `libwidgets.rlib` in `dir`, with the rustc we are
+// built against.
+fn compile_dependency(dir: &Path) -> PathBuf {
+    fs::create_dir_all(dir).unwrap();
+    let src = dir.join("widgets.rs");
+    fs::write(&src, DEPENDENCY).unwrap();
+
+    let rustc = PathBuf::from(stupid_stats::sysroot()).join("bin").join("rustc");
+    let status = Command::new(rustc)
+        .args(&["--crate-type", "rlib", "--crate-name", "widgets", "--out-dir"])
+        .arg(dir)
+        .arg(&src)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    dir.join("libwidgets.rlib")
+}
+
+fn parse_options(args: &[&str]) -> Options {
+    let args = args.iter().map(|arg| arg.to_string()).collect();
+    Options::from_args(args).unwrap().0
+}
+
+#[test]
+fn extern_with_path() {
+    let dir = env::temp_dir().join("stupid-stats-extern-path");
+    let rlib = compile_dependency(&dir);
+
+    let spec = format!("widgets={}", rlib.display());
+    let options = parse_options(&["stupid", "--extern", &spec]);
+    let stats = stupid_stats::analyze_str("lib.rs", SOURCE.to_owned(), &options).unwrap();
+    assert_eq!(stats.functions.len(), 1);
+    assert_eq!(stats.functions[0].name, "make");
+
+    // The `--extern=SPEC` form is the same.
+    let arg = format!("--extern={}", spec);
+    let options = parse_options(&["stupid", &arg]);
+    assert_eq!(options.externs, vec![spec]);
+    assert!(stupid_stats::analyze_str("lib.rs", SOURCE.to_owned(), &options).is_ok());
+}
+
+#[test]
+fn extern_from_search_path() {
+    let dir = env::temp_dir().join("stupid-stats-extern-search-path");
+    compile_dependency(&dir);
+
+    let dir = dir.display().to_string();
+    let options = parse_options(&["stupid", "--extern", "widgets", "-L", &dir]);
+    assert!(stupid_stats::analyze_str("lib.rs", SOURCE.to_owned(), &options).is_ok());
+}
+
+#[test]
+fn missing_extern() {
+    // Without `--extern`, the dependency can't be found.
+    let options = parse_options(&["stupid"]);
+    assert!(stupid_stats::analyze_str("lib.rs", SOURCE.to_owned(), &options).is_err());
+}